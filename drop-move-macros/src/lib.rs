@@ -0,0 +1,234 @@
+#![warn(missing_docs)]
+
+/*!
+
+Procedural macros for the [`drop-move`](https://docs.rs/drop-move) crate. These are re-exported
+from `drop_move` when its `macros` feature is enabled, and should be used from there.
+
+*/
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, ToTokens};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{parenthesized, Attribute, Data, DeriveInput, Fields, Ident, Path, Token, Visibility};
+
+/** Generate a pair of structures to allow moving out of `drop`, from an ordinary definition.
+
+    This is an alternative to `drop_move_wrap!` that accepts the full Rust syntax for generic
+    parameters and where clauses, including `+` bounds, const generics, and default parameters.
+    ```
+    use drop_move::{drop_move, DropMove, DropHandle};
+
+    /// Runs a function when dropped.
+    #[drop_move(inner = DropGuardInner)]
+    pub struct DropGuard<F: FnOnce() + Send> {
+        func: F,
+    }
+
+    impl<F: FnOnce() + Send> DropMove for DropGuardInner<F> {
+        fn drop_move(self_: DropHandle<Self>) {
+            (DropHandle::into_inner(self_).func)()
+        }
+    }
+
+    let mut x: u32 = 0;
+    {
+        let _guard = DropGuard::from(DropGuardInner { func: || x += 1 });
+    }
+    assert_eq!(x, 1);
+    ```
+
+    The annotated `struct` or `enum` becomes the inner structure, and the outer structure is given
+    the annotated name. Both get the same expansion as with `drop_move_wrap!`. The arguments are a
+    comma separated list of:
+
+    - `inner = inner_visibility inner_name`: Required. The name of the inner structure, and
+      optionally its visibility, which is also used for the field of the outer structure.
+    - `outer_attr(...)`: An attribute to apply only to the outer structure.
+    - `inner_attr(...)`: An attribute to apply only to the inner structure.
+    - `crate = path`: The path to the `drop_move` crate, which defaults to `::drop_move`. This is
+      needed if the crate was renamed in `Cargo.toml`, or is re-exported from another crate.
+    - Any of the options supported by `drop_move_wrap!`, e.g. `cancelable` or
+      `must_consume(Abort)`.
+
+    Additional `#[drop_move(...)]` attributes may be placed on the definition to pass more of these
+    arguments. All other attributes are applied to both structures. The `#[drop_move]` attribute
+    must come before any `#[derive]` attributes, so that they will be applied to both structures.
+    As the outer structure is `#[repr(transparent)]`, any `repr` attribute must be passed with
+    `inner_attr(...)`.
+ */
+#[proc_macro_attribute]
+pub fn drop_move(attr: TokenStream, item: TokenStream) -> TokenStream {
+    match expand(attr.into(), item.into()) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.into_compile_error().into(),
+    }
+}
+
+/// A single argument of `#[drop_move(...)]`.
+enum Arg {
+    Inner(Visibility, Ident),
+    Crate(Path),
+    OuterAttr(TokenStream2),
+    InnerAttr(TokenStream2),
+    Option(TokenStream2),
+}
+
+/// The options that are passed through to `drop_move_wrap!`.
+const OPTIONS: &[&str] = &[
+    "async_drop",
+    "cancelable",
+    "fields",
+    "try_drop",
+    "must_consume",
+    "pin",
+    "tracked",
+    "variants",
+];
+
+impl Parse for Arg {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.peek(Token![crate]) {
+            input.parse::<Token![crate]>()?;
+            input.parse::<Token![=]>()?;
+            return Ok(Arg::Crate(input.parse()?));
+        }
+
+        let key: Ident = input.parse()?;
+        if key == "inner" {
+            input.parse::<Token![=]>()?;
+            Ok(Arg::Inner(input.parse()?, input.parse()?))
+        } else if key == "outer_attr" || key == "inner_attr" {
+            let content;
+            parenthesized!(content in input);
+            let meta: TokenStream2 = content.parse()?;
+            if key == "outer_attr" {
+                Ok(Arg::OuterAttr(meta))
+            } else {
+                Ok(Arg::InnerAttr(meta))
+            }
+        } else if OPTIONS.iter().any(|option| key == option) {
+            let mut option = key.to_token_stream();
+            if input.peek(syn::token::Paren) {
+                option.extend(input.parse::<proc_macro2::Group>()?.to_token_stream());
+            }
+            Ok(Arg::Option(option))
+        } else {
+            Err(syn::Error::new(
+                key.span(),
+                format!(
+                    "expected `inner`, `outer_attr`, `inner_attr`, `crate`, or one of the options \
+                     `{}`",
+                    OPTIONS.join("`, `")
+                ),
+            ))
+        }
+    }
+}
+
+/// The parsed arguments from all `#[drop_move(...)]` attributes.
+#[derive(Default)]
+struct Args {
+    inner: Option<(Visibility, Ident)>,
+    krate: Option<Path>,
+    outer_attrs: Vec<TokenStream2>,
+    inner_attrs: Vec<TokenStream2>,
+    options: Vec<TokenStream2>,
+}
+
+impl Args {
+    fn add(&mut self, args: Punctuated<Arg, Token![,]>) -> syn::Result<()> {
+        for arg in args {
+            match arg {
+                Arg::Inner(vis, name) => {
+                    if self.inner.is_some() {
+                        return Err(syn::Error::new(name.span(), "duplicate `inner` argument"));
+                    }
+                    self.inner = Some((vis, name));
+                }
+                Arg::Crate(path) => {
+                    if self.krate.is_some() {
+                        return Err(syn::Error::new_spanned(path, "duplicate `crate` argument"));
+                    }
+                    self.krate = Some(path);
+                }
+                Arg::OuterAttr(meta) => self.outer_attrs.push(quote!(#[#meta])),
+                Arg::InnerAttr(meta) => self.inner_attrs.push(quote!(#[#meta])),
+                Arg::Option(option) => self.options.push(option),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn expand(attr: TokenStream2, item: TokenStream2) -> syn::Result<TokenStream2> {
+    let mut args = Args::default();
+    args.add(syn::parse::Parser::parse2(
+        Punctuated::<Arg, Token![,]>::parse_terminated,
+        attr,
+    )?)?;
+
+    let mut input: DeriveInput = syn::parse2(item)?;
+
+    let mut attrs: Vec<Attribute> = Vec::new();
+    for attr in input.attrs.drain(..) {
+        if attr.path().is_ident("drop_move") {
+            args.add(attr.parse_args_with(Punctuated::<Arg, Token![,]>::parse_terminated)?)?;
+        } else {
+            attrs.push(attr);
+        }
+    }
+
+    let (inner_vis, inner_name) = args.inner.ok_or_else(|| {
+        syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "missing `inner = InnerName` argument",
+        )
+    })?;
+    let krate = match args.krate {
+        Some(path) => path.into_token_stream(),
+        None => quote!(::drop_move),
+    };
+    let outer_attrs = args.outer_attrs;
+    let inner_attrs = args.inner_attrs;
+    let options = args.options;
+
+    let (decl_kind, members) = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => (quote!(struct), fields.named.to_token_stream()),
+            Fields::Unnamed(fields) => (quote!(tuple), fields.unnamed.to_token_stream()),
+            Fields::Unit => (quote!(unit), TokenStream2::new()),
+        },
+        Data::Enum(data) => (quote!(enum), data.variants.to_token_stream()),
+        Data::Union(data) => {
+            return Err(syn::Error::new_spanned(
+                data.union_token,
+                "unions are not supported",
+            ));
+        }
+    };
+
+    let vis = &input.vis;
+    let name = &input.ident;
+    let generics = &input.generics;
+    let generic_decls = &generics.params;
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+
+    Ok(quote! {
+        #krate::drop_move_wrap_options!{
+            { #(#options,)* },
+            { #(#attrs)* #(#outer_attrs)* },
+            { #(#attrs)* #(#inner_attrs)* },
+            #vis, #inner_vis,
+            #decl_kind,
+            #name, #inner_name,
+            { #type_generics },
+            { <#generic_decls> },
+            { #impl_generics },
+            { #where_clause },
+            { #members },
+        }
+    })
+}
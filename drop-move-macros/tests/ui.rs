@@ -0,0 +1,7 @@
+//! Checks that errors from `#[drop_move]` point at the offending tokens.
+
+#[test]
+fn ui() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,7 @@
+use drop_move::drop_move;
+
+#[drop_move(inner = GuardInner)]
+#[drop_move(inner = OtherInner)]
+pub struct Guard(u32);
+
+fn main() {}
@@ -0,0 +1,11 @@
+use drop_move::{drop_move, DropMove};
+
+#[drop_move(inner = GuardInner)]
+pub struct Guard<T: Clone + Send> {
+    value: T,
+    missing: Missing,
+}
+
+impl<T: Clone + Send> DropMove for GuardInner<T> {}
+
+fn main() {}
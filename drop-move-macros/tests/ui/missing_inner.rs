@@ -0,0 +1,6 @@
+use drop_move::drop_move;
+
+#[drop_move(cancelable)]
+pub struct Guard(u32);
+
+fn main() {}
@@ -0,0 +1,9 @@
+use drop_move::drop_move;
+
+#[drop_move(inner = PinFirstInner, pin, cancelable)]
+pub struct PinFirst(u32);
+
+#[drop_move(inner = CancelableFirstInner, cancelable, pin)]
+pub struct CancelableFirst(u32);
+
+fn main() {}
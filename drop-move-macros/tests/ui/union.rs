@@ -0,0 +1,8 @@
+use drop_move::drop_move;
+
+#[drop_move(inner = GuardInner)]
+pub union Guard {
+    x: u32,
+}
+
+fn main() {}
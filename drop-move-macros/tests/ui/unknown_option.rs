@@ -0,0 +1,6 @@
+use drop_move::drop_move;
+
+#[drop_move(inner = GuardInner, cancellable)]
+pub struct Guard(u32);
+
+fn main() {}
@@ -0,0 +1,11 @@
+use drop_move::{drop_move, DropMove};
+
+#[drop_move(inner = EventInner, variants(EventHandle))]
+pub enum Event {
+    Empty,
+    Wide(u8, u8, u8, u8, u8, u8, u8, u8, u8, u8, u8, u8, u8, u8, u8, u8, u8),
+}
+
+impl DropMove for EventInner {}
+
+fn main() {}
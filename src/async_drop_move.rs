@@ -0,0 +1,266 @@
+use super::*;
+use core::future::Future;
+use core::task::{Context, Poll};
+
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+#[cfg(feature = "alloc")]
+use core::pin::Pin;
+#[cfg(any(feature = "alloc", not(feature = "std")))]
+use core::ptr;
+#[cfg(feature = "alloc")]
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+/// An asynchronous variant of [`DropMove`], for when dropping needs to wait on something, e.g. a
+/// graceful shutdown of a network connection.
+///
+/// Use the `async_drop` option of [`drop_move_wrap!`] to implement [`DropMove`] in terms of this
+/// trait. This also generates a method `async fn close(self)` on the outer structure, which drops
+/// it and waits for [`drop_move_async`](Self::drop_move_async) to finish. If the value is instead
+/// dropped implicitly, then the future is passed to [`spawn_drop`].
+///
+/// ```
+/// use drop_move::{drop_move_wrap, AsyncDropMove, DropHandle};
+///
+/// drop_move_wrap! {
+///     #[drop_move(async_drop)]
+///     pub struct Connection(ConnectionInner {
+///         id: u32,
+///     });
+/// }
+///
+/// impl AsyncDropMove for ConnectionInner {
+///     async fn drop_move_async(self_: DropHandle<Self>) {
+///         let inner = DropHandle::into_inner(self_);
+///         // Send a goodbye message, and wait for the response.
+///     }
+/// }
+///
+/// async fn disconnect(connection: Connection) {
+///     connection.close().await;
+/// }
+/// ```
+pub trait AsyncDropMove: DropMoveTypes + 'static {
+    /// Drop the value by move, asynchronously.
+    fn drop_move_async(self_: DropHandle<Self>) -> impl Future<Output = ()> + 'static;
+}
+
+/// Runs the futures from [`AsyncDropMove`] values that were dropped implicitly, e.g. by spawning
+/// them onto an executor.
+///
+/// The futures are not required to be [`Send`], so they can only be run on the thread that
+/// called [`spawn`](Self::spawn). A spawner for a multithreaded executor should use its
+/// equivalent of `spawn_local`. Requires the `alloc` feature.
+#[cfg(feature = "alloc")]
+pub trait DropSpawner: Sync {
+    /// Run the future to completion.
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()>>>);
+}
+
+#[cfg(feature = "alloc")]
+static SPAWNER: AtomicPtr<&'static dyn DropSpawner> = AtomicPtr::new(ptr::null_mut());
+
+/// Register the [`DropSpawner`] used by [`spawn_drop`], replacing any previous one. Passing
+/// [`None`] goes back to blocking on the futures.
+///
+/// A trait object reference is too big to be stored atomically, so this takes a reference to one,
+/// which is easiest to get from a `static`.
+///
+/// ```
+/// use drop_move::{set_drop_spawner, DropSpawner};
+/// use std::future::Future;
+/// use std::pin::Pin;
+///
+/// struct Spawner;
+///
+/// impl DropSpawner for Spawner {
+///     fn spawn(&self, future: Pin<Box<dyn Future<Output = ()>>>) {
+///         // Pass the future to an executor.
+///     }
+/// }
+///
+/// static SPAWNER: &dyn DropSpawner = &Spawner;
+/// set_drop_spawner(Some(&SPAWNER));
+/// ```
+///
+/// Requires the `alloc` feature.
+#[cfg(feature = "alloc")]
+pub fn set_drop_spawner(spawner: Option<&'static &'static dyn DropSpawner>) {
+    let spawner = match spawner {
+        Some(spawner) => spawner as *const _ as *mut _,
+        None => ptr::null_mut(),
+    };
+    SPAWNER.store(spawner, Ordering::Release);
+}
+
+/// Drop a value asynchronously, from a synchronous context.
+///
+/// If a `DropSpawner` has been registered with `set_drop_spawner` then the future from
+/// [`drop_move_async`](AsyncDropMove::drop_move_async) is passed to it. Otherwise, this blocks the
+/// current thread until the future is finished. With the `std` feature the thread is parked while
+/// waiting, but otherwise this has to busy wait.
+pub fn spawn_drop<T: AsyncDropMove>(self_: DropHandle<T>) {
+    let future = T::drop_move_async(self_);
+
+    #[cfg(feature = "alloc")]
+    {
+        let spawner = SPAWNER.load(Ordering::Acquire);
+        if !spawner.is_null() {
+            // Safety: spawners are always `'static` references.
+            unsafe { (*spawner).spawn(Box::pin(future)) };
+            return;
+        }
+    }
+
+    block_on(future);
+}
+
+#[cfg(feature = "std")]
+fn block_on<F: Future>(future: F) -> F::Output {
+    use std::sync::Arc;
+    use std::task::{Wake, Waker};
+    use std::thread::{self, Thread};
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = core::pin::pin!(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[cfg(not(feature = "std"))]
+fn block_on<F: Future>(future: F) -> F::Output {
+    use core::task::{RawWaker, RawWakerVTable, Waker};
+
+    // Nothing needs to be woken up when busy waiting.
+    const VTABLE: RawWakerVTable = RawWakerVTable::new(|_| RAW, |_| {}, |_| {}, |_| {});
+    const RAW: RawWaker = RawWaker::new(ptr::null(), &VTABLE);
+
+    let waker = unsafe { Waker::from_raw(RAW) };
+    let mut cx = Context::from_waker(&waker);
+    let mut future = core::pin::pin!(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => core::hint::spin_loop(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    extern crate std;
+    use core::cell::RefCell;
+    use core::pin::Pin;
+    use std::rc::Rc;
+    use std::vec::Vec;
+
+    /// Returns [`Poll::Pending`] once before finishing, to check that wake ups work.
+    struct YieldOnce(bool);
+
+    impl Future for YieldOnce {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+            if self.0 {
+                Poll::Ready(())
+            } else {
+                self.0 = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    drop_move_wrap! {
+        #[drop_move(async_drop)]
+        pub struct Connection(ConnectionInner {
+            id: u32,
+            log: Rc<RefCell<Vec<u32>>>,
+        });
+    }
+
+    impl AsyncDropMove for ConnectionInner {
+        async fn drop_move_async(self_: DropHandle<Self>) {
+            let inner = DropHandle::into_inner(self_);
+            YieldOnce(false).await;
+            inner.log.borrow_mut().push(inner.id);
+        }
+    }
+
+    #[test]
+    fn close() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let connection = Connection::from(ConnectionInner {
+            id: 1,
+            log: log.clone(),
+        });
+
+        let future = connection.close();
+        assert!(log.borrow().is_empty());
+        block_on(future);
+        assert_eq!(*log.borrow(), [1]);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn spawner() {
+        std::thread_local! {
+            static QUEUE: RefCell<Vec<Pin<Box<dyn Future<Output = ()>>>>> =
+                RefCell::new(Vec::new());
+        }
+
+        /// A single threaded executor, which must be run manually.
+        struct LocalSpawner;
+
+        impl DropSpawner for LocalSpawner {
+            fn spawn(&self, future: Pin<Box<dyn Future<Output = ()>>>) {
+                QUEUE.with(|queue| queue.borrow_mut().push(future));
+            }
+        }
+
+        fn run() {
+            while let Some(future) = QUEUE.with(|queue| queue.borrow_mut().pop()) {
+                block_on(future);
+            }
+        }
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let connection = |id| {
+            Connection::from(ConnectionInner {
+                id,
+                log: log.clone(),
+            })
+        };
+
+        // Without a spawner, the drop blocks.
+        mem::drop(connection(1));
+        assert_eq!(*log.borrow(), [1]);
+
+        static SPAWNER: &dyn DropSpawner = &LocalSpawner;
+        set_drop_spawner(Some(&SPAWNER));
+        mem::drop(connection(2));
+        mem::drop(connection(3));
+        assert_eq!(*log.borrow(), [1]);
+        run();
+        assert_eq!(*log.borrow(), [1, 3, 2]);
+
+        set_drop_spawner(None);
+        mem::drop(connection(4));
+        assert_eq!(*log.borrow(), [1, 3, 2, 4]);
+    }
+}
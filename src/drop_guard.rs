@@ -1,23 +1,94 @@
 use super::*;
+use core::marker::PhantomData;
+
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+
+/// Decides whether a [`DropGuard`] will run its function when it is dropped.
+pub trait Strategy {
+    /// Check whether the function should be run.
+    fn should_run() -> bool;
+}
+
+/// Always run the function. This is the default [`Strategy`].
+#[derive(Clone, Copy, Debug)]
+pub enum Always {}
+
+impl Strategy for Always {
+    #[inline]
+    fn should_run() -> bool {
+        true
+    }
+}
+
+/// Only run the function if the thread is not panicking, i.e. if the scope exited normally.
+///
+/// Requires the `std` feature.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug)]
+pub enum OnSuccess {}
+
+#[cfg(feature = "std")]
+impl Strategy for OnSuccess {
+    #[inline]
+    fn should_run() -> bool {
+        !std::thread::panicking()
+    }
+}
+
+/// Only run the function if the thread is panicking, i.e. if the scope is being unwound.
+///
+/// Requires the `std` feature.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug)]
+pub enum OnUnwind {}
+
+#[cfg(feature = "std")]
+impl Strategy for OnUnwind {
+    #[inline]
+    fn should_run() -> bool {
+        std::thread::panicking()
+    }
+}
 
 drop_move_wrap! {
     /// Run a [`FnOnce`] function on drop.
+    ///
+    /// The [`Strategy`] `S` decides whether the function is actually run. By default it always
+    /// is, but with the `std` feature `OnSuccess` and `OnUnwind` can be used to only run it
+    /// when leaving the scope normally or by panicking, respectively.
     #[derive(Clone)]
-    pub struct DropGuard<F: FnOnce()>(DropGuardInner {
+    pub struct DropGuard<F: FnOnce(), S: Strategy = Always>(DropGuardInner {
         func: F,
+        strategy: PhantomData<S>,
     });
 }
 
-impl<F: FnOnce()> DropMove for DropGuardInner<F> {
+impl<F: FnOnce(), S: Strategy> DropMove for DropGuardInner<F, S> {
     fn drop_move(self_: DropHandle<Self>) {
-        (DropHandle::into_inner(self_).func)()
+        let inner = DropHandle::into_inner(self_);
+        if S::should_run() {
+            (inner.func)()
+        }
     }
 }
 
 impl<F: FnOnce()> DropGuard<F> {
     /// Construct from a [`FnOnce`] function.
     pub fn new(f: F) -> Self {
-        DropGuardInner { func: f }.into()
+        Self::with_strategy(f)
+    }
+}
+
+impl<F: FnOnce(), S: Strategy> DropGuard<F, S> {
+    /// Construct from a [`FnOnce`] function, which will be run on drop according to the
+    /// [`Strategy`] `S`.
+    pub fn with_strategy(f: F) -> Self {
+        DropGuardInner {
+            func: f,
+            strategy: PhantomData,
+        }
+        .into()
     }
 
     /// Extract the function.
@@ -27,7 +98,7 @@ impl<F: FnOnce()> DropGuard<F> {
     }
 }
 
-impl<F: FnOnce()> Deref for DropGuard<F> {
+impl<F: FnOnce(), S: Strategy> Deref for DropGuard<F, S> {
     type Target = F;
 
     fn deref(&self) -> &F {
@@ -35,7 +106,7 @@ impl<F: FnOnce()> Deref for DropGuard<F> {
     }
 }
 
-impl<F: FnOnce()> DerefMut for DropGuard<F> {
+impl<F: FnOnce(), S: Strategy> DerefMut for DropGuard<F, S> {
     fn deref_mut(&mut self) -> &mut F {
         &mut self.0.func
     }
@@ -47,6 +118,132 @@ impl<F: FnOnce()> From<F> for DropGuard<F> {
     }
 }
 
+/// A [`DropGuard`] holding a boxed function, so that guards for different functions have the same
+/// type, e.g. to store them in a [`Vec`](alloc::vec::Vec).
+///
+/// Requires the `alloc` feature.
+#[cfg(feature = "alloc")]
+pub type BoxDropGuard<'a, S = Always> = DropGuard<Box<dyn FnOnce() + 'a>, S>;
+
+#[cfg(feature = "alloc")]
+impl<'a, S: Strategy> DropGuard<Box<dyn FnOnce() + 'a>, S> {
+    /// Construct from any [`FnOnce`] function, by boxing it.
+    pub fn boxed(f: impl FnOnce() + 'a) -> Self {
+        Self::with_strategy(Box::new(f))
+    }
+}
+
+drop_move_wrap! {
+    /// Own a value, and pass it to a [`FnOnce`] function on drop.
+    ///
+    /// Unlike [`DropGuard`], this dereferences to the value rather than the function, so the value
+    /// can still be used while the guard is alive. The [`Strategy`] `S` works the same as for
+    /// [`DropGuard`]. If the function is not run then the value is dropped normally.
+    #[derive(Clone)]
+    pub struct DropGuardWith<T, F: FnOnce(T), S: Strategy = Always>(DropGuardWithInner {
+        value: T,
+        func: F,
+        strategy: PhantomData<S>,
+    });
+}
+
+impl<T, F: FnOnce(T), S: Strategy> DropMove for DropGuardWithInner<T, F, S> {
+    fn drop_move(self_: DropHandle<Self>) {
+        let inner = DropHandle::into_inner(self_);
+        if S::should_run() {
+            (inner.func)(inner.value)
+        }
+    }
+}
+
+impl<T, F: FnOnce(T)> DropGuardWith<T, F> {
+    /// Construct from a value and a [`FnOnce`] function that will be passed the value on drop.
+    pub fn new(value: T, f: F) -> Self {
+        Self::with_strategy(value, f)
+    }
+}
+
+impl<T, F: FnOnce(T), S: Strategy> DropGuardWith<T, F, S> {
+    /// Construct from a value and a [`FnOnce`] function, which will be passed the value on drop
+    /// according to the [`Strategy`] `S`.
+    pub fn with_strategy(value: T, f: F) -> Self {
+        DropGuardWithInner {
+            value,
+            func: f,
+            strategy: PhantomData,
+        }
+        .into()
+    }
+
+    /// Extract the value, without running the function.
+    pub fn into_inner(self) -> T {
+        self.into_parts().0
+    }
+
+    /// Extract both the value and the function, without running the function.
+    pub fn into_parts(self) -> (T, F) {
+        let inner = DropGuardWithInner::from(self);
+        (inner.value, inner.func)
+    }
+}
+
+impl<T, F: FnOnce(T), S: Strategy> Deref for DropGuardWith<T, F, S> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0.value
+    }
+}
+
+impl<T, F: FnOnce(T), S: Strategy> DerefMut for DropGuardWith<T, F, S> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0.value
+    }
+}
+
+/// Run the given statements when the current scope is exited.
+///
+/// This expands to a `let` binding of a [`DropGuard`], so it must be used as a statement. The
+/// binding is hygienic, so it cannot be accidentally dropped early or shadowed.
+/// ```
+/// use drop_move::defer;
+///
+/// let mut x = 0;
+/// {
+///     defer! { x += 1; }
+///     // x is not incremented until the scope is exited.
+/// }
+/// assert_eq!(x, 1);
+/// ```
+#[macro_export]
+macro_rules! defer {
+    ($($body:tt)*) => {
+        let _guard = $crate::DropGuard::new(|| { $($body)* });
+    };
+}
+
+/// Run the given statements when the current scope is exited by panicking.
+///
+/// This is like [`defer!`], but uses the [`OnUnwind`] strategy. Requires the `std` feature.
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! defer_on_unwind {
+    ($($body:tt)*) => {
+        let _guard = $crate::DropGuard::<_, $crate::OnUnwind>::with_strategy(|| { $($body)* });
+    };
+}
+
+/// Run the given statements when the current scope is exited without panicking.
+///
+/// This is like [`defer!`], but uses the [`OnSuccess`] strategy. Requires the `std` feature.
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! defer_on_success {
+    ($($body:tt)*) => {
+        let _guard = $crate::DropGuard::<_, $crate::OnSuccess>::with_strategy(|| { $($body)* });
+    };
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -86,4 +283,114 @@ mod test {
 
         assert_eq!(x, 3);
     }
+
+    #[test]
+    fn test_from() {
+        let mut x: u32 = 0;
+        {
+            let _guard = DropGuard::from(|| x += 1);
+        }
+        assert_eq!(x, 1);
+    }
+
+    #[test]
+    fn test_with() {
+        use std::vec::Vec;
+
+        let mut out = Vec::new();
+        {
+            let mut guard = DropGuardWith::new(Vec::new(), |v| out = v);
+            guard.push(1);
+            guard.push(2);
+            assert_eq!(guard.len(), 2);
+        }
+        assert_eq!(out, [1, 2]);
+
+        let guard = DropGuardWith::new(Box::new(3), |_| panic!("disarmed"));
+        assert_eq!(*guard.into_inner(), 3);
+
+        let guard = DropGuardWith::new(4, |x: u32| out.push(x));
+        let (x, f) = guard.into_parts();
+        f(x + 1);
+        assert_eq!(out, [1, 2, 5]);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_boxed() {
+        use core::cell::RefCell;
+        use std::vec::Vec;
+
+        let order = RefCell::new(Vec::new());
+        {
+            let order = &order;
+            let mut guards: Vec<BoxDropGuard> = Vec::new();
+            guards.push(DropGuard::boxed(|| order.borrow_mut().push(1)));
+            let two = 2;
+            guards.push(DropGuard::boxed(move || order.borrow_mut().push(two)));
+            guards.reverse();
+        }
+        assert_eq!(*order.borrow(), [2, 1]);
+    }
+
+    #[test]
+    fn test_defer() {
+        use core::cell::RefCell;
+        use std::vec::Vec;
+
+        let order = RefCell::new(Vec::new());
+        {
+            defer! { order.borrow_mut().push(1); }
+            defer! { order.borrow_mut().push(2); }
+            order.borrow_mut().push(0);
+        }
+        assert_eq!(*order.borrow(), [0, 2, 1]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_defer_strategies() {
+        use core::cell::Cell;
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let success = Cell::new(0);
+        let unwind = Cell::new(0);
+        let run = |panic: bool| {
+            defer_on_success! { success.set(success.get() + 1) }
+            defer_on_unwind! { unwind.set(unwind.get() + 1) }
+            if panic {
+                panic!("unwinding");
+            }
+        };
+
+        run(false);
+        assert!(catch_unwind(AssertUnwindSafe(|| run(true))).is_err());
+        assert_eq!((success.get(), unwind.get()), (1, 1));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_strategies() {
+        use core::cell::Cell;
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let success = Cell::new(0);
+        let unwind = Cell::new(0);
+        let always = Cell::new(0);
+        let run = |panic: bool| {
+            let _success =
+                DropGuard::<_, OnSuccess>::with_strategy(|| success.set(success.get() + 1));
+            let _unwind = DropGuard::<_, OnUnwind>::with_strategy(|| unwind.set(unwind.get() + 1));
+            let _always = DropGuard::new(|| always.set(always.get() + 1));
+            if panic {
+                panic!("unwinding");
+            }
+        };
+
+        run(false);
+        assert_eq!((success.get(), unwind.get(), always.get()), (1, 0, 1));
+
+        assert!(catch_unwind(AssertUnwindSafe(|| run(true))).is_err());
+        assert_eq!((success.get(), unwind.get(), always.get()), (1, 1, 2));
+    }
 }
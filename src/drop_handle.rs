@@ -1,4 +1,7 @@
 use super::*;
+#[cfg(feature = "std")]
+use core::cell::Cell;
+use core::ptr;
 
 /// A wrapper of [`T::Outer`](DropMoveTypes::Outer) that will drop by converting to `T` then
 /// dropping, rather than dropping the `T::Outer`.
@@ -12,16 +15,12 @@ use super::*;
 pub struct DropHandle<T: DropMoveTypes>(ManuallyDrop<T::Outer>);
 
 impl<T: DropMoveTypes> DropHandle<T> {
-    unsafe fn take(self_: &mut Self) -> T::Outer {
-        ManuallyDrop::take(&mut self_.0)
-    }
-
     /// Convert to the inner structure `T`.
     ///
     /// This is an associated function so that will not conflict with any methods of `T::Outer`,
     /// which are accessible through [`Deref`].
     pub fn into_inner(self_: Self) -> T {
-        Self::into_outer(self_).into()
+        outer_into_inner(Self::into_outer(self_))
     }
 
     /// Convert to the outer structure `T::Outer`. Be careful when using this function, as it is
@@ -30,10 +29,10 @@ impl<T: DropMoveTypes> DropHandle<T> {
     ///
     /// This is an associated function so that will not conflict with any methods of `T::Outer`,
     /// which are accessible through [`Deref`].
-    pub fn into_outer(mut self_: Self) -> T::Outer {
-        let outer = unsafe { Self::take(&mut self_) };
-        mem::forget(self_);
-        outer
+    pub fn into_outer(self_: Self) -> T::Outer {
+        // The handle is disarmed before moving out, so nothing can drop the outer structure twice.
+        let self_ = ManuallyDrop::new(self_);
+        unsafe { ptr::read(&*self_.0) }
     }
 }
 
@@ -45,12 +44,71 @@ impl<T: DropMoveTypes> From<T> for DropHandle<T> {
 
 impl<T: DropMoveTypes> Drop for DropHandle<T> {
     fn drop(&mut self) {
-        let _inner: T = unsafe { Self::take(self) }.into();
+        let _inner: T = outer_into_inner(unsafe { ManuallyDrop::take(&mut self.0) });
 
-        // Dropping the inner type avoids the drop calling drop infinite loop.
+        // Dropping the inner type avoids the drop calling drop infinite loop. If the conversion
+        // panics then the outer structure is dropped instead, but nothing can be dropped twice as
+        // the handle no longer owns it.
     }
 }
 
+#[cfg(feature = "std")]
+std::thread_local! {
+    /// The name of the inner structure whose outer structure is being converted on this thread, if
+    /// no wrapper has been skipped for the conversion yet.
+    static CONVERTING: Cell<Option<&'static str>> = const { Cell::new(None) };
+}
+
+/// Convert the outer structure to the inner structure. If a hand-written conversion panics, it
+/// drops the outer structure while unwinding, which must not call `drop_move` again, as it is
+/// already running. With the `std` feature this is marked for [`conversion_unwinding`] to see.
+///
+/// The conversion takes the outer structure by move, so its address is not known when it is
+/// dropped. Instead, the mark is for the type, and is cleared by the first wrapper of that type to
+/// be dropped while unwinding. The outer structure is dropped last, as it is a parameter, so this
+/// can only be a different value if the conversion has a local of the same type.
+fn outer_into_inner<T: DropMoveTypes>(outer: T::Outer) -> T {
+    #[cfg(feature = "std")]
+    {
+        struct Restore(Option<&'static str>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                CONVERTING.with(|converting| converting.set(self.0));
+            }
+        }
+
+        let name = core::any::type_name::<T>();
+        let _restore = Restore(CONVERTING.with(|converting| converting.replace(Some(name))));
+        outer.into()
+    }
+
+    #[cfg(not(feature = "std"))]
+    outer.into()
+}
+
+/// Check whether a wrapper of `T` is being dropped because converting from its outer structure
+/// panicked, in which case it must not call `drop_move`. This is true for at most one wrapper per
+/// conversion, and is always false without the `std` feature.
+#[cfg_attr(not(feature = "std"), allow(clippy::extra_unused_type_parameters))]
+pub(crate) fn conversion_unwinding<T>() -> bool {
+    #[cfg(feature = "std")]
+    {
+        let name = core::any::type_name::<T>();
+        std::thread::panicking()
+            && CONVERTING.with(|converting| {
+                let matches = converting.get() == Some(name);
+                if matches {
+                    converting.set(None);
+                }
+                matches
+            })
+    }
+
+    #[cfg(not(feature = "std"))]
+    false
+}
+
 impl<T: DropMoveTypes> Deref for DropHandle<T> {
     type Target = T::Outer;
     fn deref(&self) -> &T::Outer {
@@ -0,0 +1,166 @@
+use super::*;
+use alloc::boxed::Box;
+
+/// An object safe version of [`DropMove`], for dropping a boxed inner structure by move.
+///
+/// [`DropMove`] requires [`Sized`], so it cannot be used through a trait object. This is
+/// implemented for every inner structure that implements [`DropMove`], and is used by
+/// [`DropMoveBox`] to hold inner structures of different types.
+///
+/// Requires the `alloc` feature.
+pub trait DropMoveDyn {
+    /// Move the inner structure out of the box, and drop it with
+    /// [`drop_move`](DropMove::drop_move), following its [`PANIC_POLICY`](DropMove::PANIC_POLICY).
+    fn drop_move_boxed(self: Box<Self>);
+}
+
+impl<T: DropMove> DropMoveDyn for T {
+    fn drop_move_boxed(self: Box<Self>) {
+        let drop_ref: DropHandle<T> = DropHandle::from(*self);
+        T::on_drop_move(&drop_ref);
+        T::PANIC_POLICY.run(|| DropMove::drop_move(drop_ref));
+    }
+}
+
+drop_move_wrap! {
+    /// A boxed inner structure, which will be dropped with [`drop_move`](DropMove::drop_move).
+    ///
+    /// The default `T` of `dyn DropMoveDyn` allows resources of different types to be stored
+    /// together, each running its own `drop_move` when dropped.
+    ///
+    /// ```
+    /// use drop_move::{drop_move_wrap, DropHandle, DropMove, DropMoveBox};
+    /// use std::cell::RefCell;
+    ///
+    /// thread_local! {
+    ///     static CLOSED: RefCell<Vec<String>> = RefCell::new(Vec::new());
+    /// }
+    ///
+    /// drop_move_wrap! {
+    ///     pub struct File(FileInner { path: String });
+    /// }
+    ///
+    /// impl DropMove for FileInner {
+    ///     fn drop_move(self_: DropHandle<Self>) {
+    ///         let path = DropHandle::into_inner(self_).path;
+    ///         CLOSED.with(|closed| closed.borrow_mut().push(path));
+    ///     }
+    /// }
+    ///
+    /// drop_move_wrap! {
+    ///     pub struct Socket(SocketInner { port: u16 });
+    /// }
+    ///
+    /// impl DropMove for SocketInner {
+    ///     fn drop_move(self_: DropHandle<Self>) {
+    ///         let port = self_.0.port.to_string();
+    ///         CLOSED.with(|closed| closed.borrow_mut().push(port));
+    ///     }
+    /// }
+    ///
+    /// let resources: Vec<DropMoveBox> = vec![
+    ///     DropMoveBox::new(FileInner { path: "log.txt".to_owned() }),
+    ///     DropMoveBox::new(SocketInner { port: 80 }),
+    /// ];
+    /// drop(resources);
+    /// CLOSED.with(|closed| assert_eq!(*closed.borrow(), ["log.txt", "80"]));
+    /// ```
+    ///
+    /// Requires the `alloc` feature.
+    pub struct DropMoveBox<T: ?Sized + DropMoveDyn = dyn DropMoveDyn>(DropMoveBoxInner(Box<T>));
+}
+
+impl<T: ?Sized + DropMoveDyn> DropMove for DropMoveBoxInner<T> {
+    fn drop_move(self_: DropHandle<Self>) {
+        DropHandle::into_inner(self_).0.drop_move_boxed();
+    }
+}
+
+impl<'a> DropMoveBox<dyn DropMoveDyn + 'a> {
+    /// Box an inner structure as a trait object.
+    pub fn new(x: impl DropMoveDyn + 'a) -> Self {
+        Self::from_box(Box::new(x))
+    }
+}
+
+impl<T: ?Sized + DropMoveDyn> DropMoveBox<T> {
+    /// Wrap a boxed inner structure, so that it will be dropped with `drop_move`. This can be used
+    /// to hold trait objects with extra bounds, such as `dyn DropMoveDyn + Send`.
+    pub fn from_box(x: Box<T>) -> Self {
+        Self::from(DropMoveBoxInner(x))
+    }
+
+    /// Convert back into the box, so that `drop_move` will not be called.
+    ///
+    /// This is an associated function so that will not conflict with any methods of `T`, which
+    /// are accessible through [`Deref`].
+    pub fn into_box(self_: Self) -> Box<T> {
+        DropMoveBoxInner::from(self_).0
+    }
+}
+
+impl<T: ?Sized + DropMoveDyn> Deref for DropMoveBox<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.0 .0
+    }
+}
+
+impl<T: ?Sized + DropMoveDyn> DerefMut for DropMoveBox<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0 .0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    extern crate std;
+    use core::cell::RefCell;
+    use std::boxed::Box;
+    use std::rc::Rc;
+    use std::vec;
+    use std::vec::Vec;
+
+    drop_move_wrap! {
+        pub struct Named(NamedInner(&'static str, Rc<RefCell<Vec<&'static str>>>));
+    }
+
+    impl DropMove for NamedInner {
+        fn drop_move(self_: DropHandle<Self>) {
+            let inner = DropHandle::into_inner(self_);
+            inner.1.borrow_mut().push(inner.0);
+        }
+    }
+
+    drop_move_wrap! {
+        pub struct Counted<T>(CountedInner(T, Rc<RefCell<Vec<&'static str>>>));
+    }
+
+    impl<T> DropMove for CountedInner<T> {
+        fn drop_move(self_: DropHandle<Self>) {
+            self_.0 .1.borrow_mut().push("counted");
+        }
+    }
+
+    #[test]
+    fn boxed() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let resources = vec![
+            DropMoveBox::new(NamedInner("a", log.clone())),
+            DropMoveBox::new(CountedInner(5u32, log.clone())),
+            DropMoveBox::new(NamedInner("b", log.clone())),
+        ];
+        mem::drop(resources);
+        assert_eq!(*log.borrow(), ["a", "counted", "b"]);
+
+        let skipped = DropMoveBox::new(NamedInner("skipped", log.clone()));
+        mem::drop(DropMoveBox::into_box(skipped));
+
+        let nested = DropMoveBox::new(DropMoveBoxInner(Box::new(NamedInner("c", log.clone()))));
+        mem::drop(nested);
+        assert_eq!(*log.borrow(), ["a", "counted", "b", "c"]);
+        assert_eq!(Rc::strong_count(&log), 1);
+    }
+}
@@ -1,20 +1,22 @@
 use super::*;
+use core::cmp::Ordering;
+use core::hash::{Hash, Hasher};
+use core::ptr;
 
 /// A wrapper around the inner structure `T` that calls [`drop_move`](DropMove::drop_move) when it
 /// is dropped.
 ///
 /// The inner structure members can be borrowed using the [`Deref`] and [`DerefMut`]
 /// implementations, or be moved with `into_inner`.
+///
+/// This is `#[repr(transparent)]`, so it is guaranteed to have the same layout as `T`.
 #[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
 pub struct DropMoveWrapper<T: DropMove>(ManuallyDrop<T>);
 
 impl<T: DropMove> DropMoveWrapper<T> {
-    unsafe fn take(self_: &mut Self) -> T {
-        ManuallyDrop::take(&mut self_.0)
-    }
-
     /// Wrap the inner structure, so that it will be dropped with `drop_move`.
-    pub fn new(x: T) -> Self {
+    pub const fn new(x: T) -> Self {
         DropMoveWrapper(ManuallyDrop::new(x))
     }
 
@@ -22,10 +24,18 @@ impl<T: DropMove> DropMoveWrapper<T> {
     ///
     /// This is an associated function so that will not conflict with any methods of the inner type,
     /// which are accessible through [`Deref`].
-    pub fn into_inner(mut self_: Self) -> T {
-        let inner = unsafe { Self::take(&mut self_) };
-        mem::forget(self_);
-        inner
+    pub fn into_inner(self_: Self) -> T {
+        // The wrapper is disarmed before moving out, so nothing can drop the inner structure twice.
+        let self_ = ManuallyDrop::new(self_);
+        unsafe { ptr::read(&*self_.0) }
+    }
+
+    /// Disarm the wrapper, so that [`drop_move`](DropMove::drop_move) will not be called.
+    ///
+    /// This is the same as [`into_inner`](Self::into_inner). The returned inner structure will
+    /// still drop each of its members when it goes out of scope, but will skip `drop_move`.
+    pub fn defuse(self_: Self) -> T {
+        Self::into_inner(self_)
     }
 }
 
@@ -44,8 +54,170 @@ impl<T: DropMove> DerefMut for DropMoveWrapper<T> {
 
 impl<T: DropMove> Drop for DropMoveWrapper<T> {
     fn drop(&mut self) {
-        let drop_ref: DropHandle<T> = From::from(unsafe { Self::take(self) });
-        DropMove::drop_move(drop_ref);
+        let inner = unsafe { ManuallyDrop::take(&mut self.0) };
+        if !conversion_unwinding::<T>() {
+            let drop_ref: DropHandle<T> = From::from(inner);
+            T::on_drop_move(&drop_ref);
+            T::PANIC_POLICY.run(|| DropMove::drop_move(drop_ref));
+        }
+    }
+}
+
+/// A variant of [`DropMoveWrapper`] that can be canceled in place, so that
+/// [`drop_move`](DropMove::drop_move) will not be called when it is dropped.
+///
+/// This stores a flag alongside the inner structure, so it is only used by [`drop_move_wrap!`] if
+/// the `cancelable` option is given. Canceling is useful when the outer structure is still
+/// borrowed, e.g. to conditionally disarm a guard. If it is canceled then each member of the inner
+/// structure will be dropped normally instead.
+///
+/// Unlike the other wrappers, this does not have the same layout as `T`. The flag is ignored when
+/// comparing or hashing, which only looks at the inner structure.
+#[derive(Clone, Debug, Default)]
+pub struct CancelableDropMoveWrapper<T: DropMove>(ManuallyDrop<T>, bool);
+
+impl<T: DropMove> CancelableDropMoveWrapper<T> {
+    /// Wrap the inner structure, so that it will be dropped with `drop_move` unless canceled.
+    pub const fn new(x: T) -> Self {
+        CancelableDropMoveWrapper(ManuallyDrop::new(x), false)
+    }
+
+    /// Convert into the inner structure `T`.
+    ///
+    /// This is an associated function so that will not conflict with any methods of the inner type,
+    /// which are accessible through [`Deref`].
+    pub fn into_inner(self_: Self) -> T {
+        // The wrapper is disarmed before moving out, so nothing can drop the inner structure twice.
+        let self_ = ManuallyDrop::new(self_);
+        unsafe { ptr::read(&*self_.0) }
+    }
+
+    /// Disarm the wrapper, so that [`drop_move`](DropMove::drop_move) will not be called. This is
+    /// the same as [`into_inner`](Self::into_inner).
+    pub fn defuse(self_: Self) -> T {
+        Self::into_inner(self_)
+    }
+
+    /// Disarm the wrapper in place, so that [`drop_move`](DropMove::drop_move) will not be called
+    /// when it is dropped.
+    pub fn cancel(self_: &mut Self) {
+        self_.1 = true;
+    }
+
+    /// Check whether [`cancel`](Self::cancel) has been called.
+    pub fn is_canceled(self_: &Self) -> bool {
+        self_.1
+    }
+}
+
+impl<T: DropMove> Deref for CancelableDropMoveWrapper<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        self.0.deref()
+    }
+}
+
+impl<T: DropMove> DerefMut for CancelableDropMoveWrapper<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0.deref_mut()
+    }
+}
+
+impl<T: DropMove + PartialEq> PartialEq for CancelableDropMoveWrapper<T> {
+    fn eq(&self, other: &Self) -> bool {
+        *self.0 == *other.0
+    }
+}
+
+impl<T: DropMove + Eq> Eq for CancelableDropMoveWrapper<T> {}
+
+impl<T: DropMove + PartialOrd> PartialOrd for CancelableDropMoveWrapper<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        (*self.0).partial_cmp(&*other.0)
+    }
+}
+
+impl<T: DropMove + Ord> Ord for CancelableDropMoveWrapper<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (*self.0).cmp(&*other.0)
+    }
+}
+
+impl<T: DropMove + Hash> Hash for CancelableDropMoveWrapper<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (*self.0).hash(state)
+    }
+}
+
+impl<T: DropMove> Drop for CancelableDropMoveWrapper<T> {
+    fn drop(&mut self) {
+        let inner = unsafe { ManuallyDrop::take(&mut self.0) };
+        if !self.1 && !conversion_unwinding::<T>() {
+            let drop_ref: DropHandle<T> = From::from(inner);
+            T::on_drop_move(&drop_ref);
+            T::PANIC_POLICY.run(|| DropMove::drop_move(drop_ref));
+        }
+    }
+}
+
+/// A variant of [`DropMoveWrapper`] for structures that may be pinned, which calls
+/// [`drop_move_pinned`](PinDropMove::drop_move_pinned) when it is dropped.
+///
+/// This is used by [`drop_move_wrap!`] if the `pin` option is given. The inner structure is never
+/// moved when it is dropped, so [`as_pin_mut`](Self::as_pin_mut) can safely project a pinned
+/// wrapper to the inner structure. Unpinned wrappers still give out `&mut T` through [`DerefMut`],
+/// and can be converted with [`into_inner`](Self::into_inner).
+///
+/// This is `#[repr(transparent)]`, so it is guaranteed to have the same layout as `T`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
+pub struct PinDropMoveWrapper<T: PinDropMove>(ManuallyDrop<T>);
+
+impl<T: PinDropMove> PinDropMoveWrapper<T> {
+    /// Wrap the inner structure, so that it will be dropped with `drop_move_pinned`.
+    pub const fn new(x: T) -> Self {
+        PinDropMoveWrapper(ManuallyDrop::new(x))
+    }
+
+    /// Convert into the inner structure `T`. This takes the wrapper by value, so it cannot have
+    /// been pinned unless it is [`Unpin`].
+    ///
+    /// This is an associated function so that will not conflict with any methods of the inner type,
+    /// which are accessible through [`Deref`].
+    pub fn into_inner(self_: Self) -> T {
+        let self_ = ManuallyDrop::new(self_);
+        unsafe { ptr::read(&*self_.0) }
+    }
+
+    /// Disarm the wrapper, so that [`drop_move_pinned`](PinDropMove::drop_move_pinned) will not be
+    /// called. This is the same as [`into_inner`](Self::into_inner).
+    pub fn defuse(self_: Self) -> T {
+        Self::into_inner(self_)
+    }
+
+    /// Project a pinned wrapper to the pinned inner structure.
+    pub fn as_pin_mut(self_: Pin<&mut Self>) -> Pin<&mut T> {
+        unsafe { self_.map_unchecked_mut(|x| x.0.deref_mut()) }
+    }
+}
+
+impl<T: PinDropMove> Deref for PinDropMoveWrapper<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        self.0.deref()
+    }
+}
+
+impl<T: PinDropMove> DerefMut for PinDropMoveWrapper<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0.deref_mut()
+    }
+}
+
+impl<T: PinDropMove> Drop for PinDropMoveWrapper<T> {
+    fn drop(&mut self) {
+        // Safety: the wrapper is being dropped, so the inner structure will not be used again.
+        unsafe { PinDropHandle::drop_in_place(self.0.deref_mut()) };
     }
 }
 
@@ -53,6 +225,7 @@ impl<T: DropMove> Drop for DropMoveWrapper<T> {
 
     The syntax is roughly:
     ```ignore
+    #[drop_move(options)]
     #[shared_attributes]
     {
         #[outer_only_attributes]
@@ -64,13 +237,14 @@ impl<T: DropMove> Drop for DropMoveWrapper<T> {
     ) where ...;
     ```
 
-    Tuple `structs` can be used by swapping `{ members }` for `( members )`, and enumerations by
+    Tuple `structs` can be used by swapping `{ members }` for `( members )`, unit `structs` by
+    leaving out the members entirely, as in `struct Token(TokenInner);`, and enumerations by
     changing `struct` to `enum`. The attributes, generic parameters, and where clause are optional
-    and can be omitted. The syntax for the generic parameters and bounds is almost the same as
-    normal; however, due to
-    [limitations](https://internals.rust-lang.org/t/allow-to-follow-path-fragments-in-declarative-macros/13676)
-    in macro parsing they do not support the `+` syntax for specifying multiple traits. Instead, you
-    should use `:`, so e.g. `T: Clone : Eq` means that `T` must implement both `Clone` and `Eq`.
+    and can be omitted. The generic parameters and where clause use the normal syntax, e.g.
+    `T: Clone + Eq + 'a`, `A: Alloc = Global`, or `const N: usize = 4`. Defaults are only used for
+    the structure definitions, and are left out of the generated `impl`s. For backwards
+    compatibility, `:` may also be used in place of `+` to separate bounds, so `T: Clone : Eq`
+    means that `T` must implement both `Clone` and `Eq`.
 
     The macro expands to two structures: `struct outer_name` wrapping a [`DropMoveWrapper`]
     containing `struct inner_name`, which holds the actual members. All attributes in
@@ -79,9 +253,52 @@ impl<T: DropMove> Drop for DropMoveWrapper<T> {
     The inner visibility is applied to both the definition of the inner `struct` and the field of
     the outer `struct` that wraps it, so if it is `pub` then anyone will be able to access it.
 
+    The outer structure is `#[repr(transparent)]`, and unless the `cancelable` option is given it is
+    guaranteed to have the same layout as the inner structure, so it can be passed over FFI in place
+    of it. For structures without generic parameters this is also checked by a compile time
+    assertion. Any `repr` attribute must therefore be placed in `inner_only_attributes`, as it would
+    conflict if applied to the outer structure.
+
     This macro also implements [`From`] to convert back and forth between the inner and outer
     structures, and [`DropMoveTypes`] to tell [`DropMoveWrapper`] the relationship between the inner
-    and outer structures.
+    and outer structures. As trait methods cannot be called in `const` contexts, the outer structure
+    also gets a `const fn new_from_inner(inner: Inner) -> Self`, which allows it to be used in
+    `static` items. The outer structure also gets an associated function
+    `fn defuse(self_: Self) -> Inner`, which converts it to the inner structure without calling
+    `drop_move`. Both have the inner visibility, as they expose the inner structure. If the inner
+    structure is a unit `struct`, it also adds a `const fn new()` to the outer structure, so zero
+    sized tokens can be created in `const` contexts. [`Default`] is not implemented, so that it can
+    be derived in the shared attributes.
+
+    The `#[drop_move(...)]` attributes are not applied to either structure, and instead hold a comma
+    separated list of options for the macro. The supported options are:
+
+    - `async_drop`: Implement [`DropMove`] using [`AsyncDropMove`] and [`spawn_drop`], and add a
+      method `async fn close(self)` to the outer structure that waits for
+      [`drop_move_async`](AsyncDropMove::drop_move_async) to finish.
+    - `cancelable`: Use a [`CancelableDropMoveWrapper`] instead of a [`DropMoveWrapper`], and add
+      the associated functions `fn cancel(self_: &mut Self)` and
+      `fn is_canceled(self_: &Self) -> bool` to the outer structure, with the inner visibility,
+      so that `drop_move` can be skipped while the structure is still borrowed.
+    - `fields`: Implement [`DropMoveFields`], so that a [`PartialHandle`] can be used to move
+      individual fields out in `drop_move`. This requires the inner structure to have named fields.
+    - `must_consume` or `must_consume(Action)`: Implement [`DropMove`] to take an action when the
+      value is dropped, and add a method `fn consume(self) -> Inner` to the outer structure for
+      getting the inner structure without triggering it. `Action` must be one of the
+      [`ConsumeAction`]s in [`must_consume`], and defaults to [`Panic`](must_consume::Panic).
+    - `pin`: Use a [`PinDropMoveWrapper`] instead of a [`DropMoveWrapper`], so that the structure
+      can be pinned. See [`PinDropMove`] for the generated projection methods. This cannot be
+      combined with `cancelable`.
+    - `tracked(field)`: Record each call to `drop_move` with the `testing::Tracked` value in
+      `field` of the inner structure, which may be a name or a tuple index, so that a
+      `testing::DropTracker` can check that it happened. This requires the `testing` feature.
+    - `try_drop`: Implement [`DropMove`] using [`TryDropMove`], and add a method
+      `fn close(self) -> Result<(), Error>` to the outer structure that returns the error from
+      [`try_drop_move`](TryDropMove::try_drop_move) instead of passing it to
+      [`on_drop_error`](TryDropMove::on_drop_error).
+    - `variants(HandleName)`: Generate an `enum HandleName` with a [`VariantHandle`] for each
+      variant of the inner `enum`, which can be created from a [`DropHandle`]. This requires the
+      inner structure to be an `enum`, and its tuple variants to have at most 16 fields.
 
     Note that this macro is implemented internally using a few others, which may appear in compiler
     error messages. These all have names prefixed with `drop_move_wrap`.
@@ -89,244 +306,1531 @@ impl<T: DropMove> Drop for DropMoveWrapper<T> {
 #[macro_export]
 macro_rules! drop_move_wrap {
     {$($def:tt)+} => {
-        $crate::drop_move_wrap_match!{$($def)+}
+        $crate::drop_move_wrap_match!{@attrs [], [], $($def)+}
     };
 }
 
 #[doc(hidden)]
 #[macro_export]
 macro_rules! drop_move_wrap_match {
-    // struct/enum {}
+    // Attributes are parsed one at a time to separate out the `#[drop_move(...)]` options.
+    {
+        @attrs [$($options:tt)*], [$($attrs:tt)*],
+        #[drop_move($($option:tt)*)] $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_match!{
+            @attrs [$($options)* $($option)*,], [$($attrs)*], $($rest)*
+        }
+    };
+
+    {
+        @attrs [$($options:tt)*], [$($attrs:tt)*],
+        #[$attr:meta] $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_match!{
+            @attrs [$($options)*], [$($attrs)* #[$attr]], $($rest)*
+        }
+    };
+
+    // struct
+    {
+        @attrs [$($options:tt)*], [$($attrs:tt)*],
+        $({$(#[$outer_only_attrs:meta])+})?
+        $vis:vis struct $name:ident $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_generics!{
+            {
+                { $($options)* },
+                { $($attrs)* $($(#[$outer_only_attrs])+)? },
+                { $($attrs)* },
+                $vis, struct, $name,
+            },
+            $($rest)*
+        }
+    };
+
+    // enum
     {
-        $(#[$attrs:meta])*
+        @attrs [$($options:tt)*], [$($attrs:tt)*],
         $({$(#[$outer_only_attrs:meta])+})?
-        $vis:vis struct $name:ident $(<
-            $($lifetimes:lifetime $(: $lifetime_bounds1:lifetime $(+ $lifetime_bounds2:lifetime)*)?),*
-            $(,)?
-            $($types:ident $(:
-                $($lifetime_ty_bounds1:lifetime)? $($type_bounds1:path)?
-                $(: $($lifetime_ty_bounds2:lifetime)? $($type_bounds2:path)?)*
-            )?),*
-            $(,)?
-        >)?(
+        $vis:vis enum $name:ident $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_generics!{
+            {
+                { $($options)* },
+                { $($attrs)* $($(#[$outer_only_attrs])+)? },
+                { $($attrs)* },
+                $vis, enum, $name,
+            },
+            $($rest)*
+        }
+    };
+
+    // struct {}, after the generic parameters have been parsed.
+    {
+        @body {
+            { $($options:tt)* },
+            { $($attrs:tt)* },
+            { $($shared_attrs:tt)* },
+            $vis:vis, struct, $name:ident,
+        },
+        { $($generic_params:tt)* },
+        { $($generic_decls:tt)* },
+        { $($generic_bounds:tt)* },
+        (
             $(#[$inner_only_attrs:meta])*
             $inner_vis:vis $inner_name:ident {$($members:tt)*}
-        ) $(where
-            $(
-                $($lifetime_wheres:lifetime)?
-                $($(for<($for_lt:lifetime),*>)? $type_wheres:ty)?
-                :
-                $($lifetime_ty_bounds3:lifetime)? $($type_bounds3:path)?
-                $(: $($lifetime_ty_bounds4:lifetime)? $($type_bounds4:path)?)*
-            ),*
-            $(,)?
-        )?;
-    } => {
-        $crate::drop_move_wrap_transcribe!{
-            { $(#[$attrs])* $($(#[$outer_only_attrs])+)? },
-            { $(#[$attrs])* $(#[$inner_only_attrs])* },
-            $vis, $inner_vis,
-            struct,
-            $name, $inner_name,
-            { $(<$($lifetimes, )*$($types, )*>)? },
-            { $(<
-                $($lifetimes $(: $lifetime_bounds1 $(+ $lifetime_bounds2)*)?, )*
-                $($types $(:
-                    $($type_bounds1)? $($lifetime_ty_bounds1)?
-                    $(+ $($type_bounds2)? $($lifetime_ty_bounds2)?)*
-                )?, )*
-            >)? },
-            { $(where
-                $(
-                    $($lifetime_wheres)?
-                    $($(for<($for_lt),*>)? $type_wheres)?
-                    :
-                    $($type_bounds3)? $($lifetime_ty_bounds3)?
-                    $(+ $($type_bounds4)? $($lifetime_ty_bounds4)?)*
-                ,)*
-            )? },
-            { $($members)* },
+        ) $($where_clause:tt)*
+    } => {
+        $crate::drop_move_wrap_where!{
+            {
+                { $($options)* },
+                { $($attrs)* },
+                { $($shared_attrs)* $(#[$inner_only_attrs])* },
+                $vis, $inner_vis,
+                struct,
+                $name, $inner_name,
+                { $($generic_params)* },
+                { $($generic_decls)* },
+                { $($generic_bounds)* },
+            },
+            { { $($members)* }, },
+            $($where_clause)*
         }
     };
 
-    // struct ()
+    // struct (), after the generic parameters have been parsed.
     {
-        $(#[$attrs:meta])*
-        $({$(#[$outer_only_attrs:meta])+})?
-        $vis:vis struct $name:ident $(<
-            $($lifetimes:lifetime $(: $lifetime_bounds1:lifetime $(+ $lifetime_bounds2:lifetime)*)?),*
-            $(,)?
-            $($types:ident $(:
-                $($lifetime_ty_bounds1:lifetime)? $($type_bounds1:path)?
-                $(: $($lifetime_ty_bounds2:lifetime)? $($type_bounds2:path)?)*
-            )?),*
-            $(,)?
-        >)?(
+        @body {
+            { $($options:tt)* },
+            { $($attrs:tt)* },
+            { $($shared_attrs:tt)* },
+            $vis:vis, struct, $name:ident,
+        },
+        { $($generic_params:tt)* },
+        { $($generic_decls:tt)* },
+        { $($generic_bounds:tt)* },
+        (
             $(#[$inner_only_attrs:meta])*
             $inner_vis:vis $inner_name:ident ($($members:tt)*)
-        ) $(where
-            $(
-                $($lifetime_wheres:lifetime)?
-                $($(for<($for_lt:lifetime),*>)? $type_wheres:ty)?
-                :
-                $($lifetime_ty_bounds3:lifetime)? $($type_bounds3:path)?
-                $(: $($lifetime_ty_bounds4:lifetime)? $($type_bounds4:path)?)*
-            ),*
-            $(,)?
-        )?;
-    } => {
-        $crate::drop_move_wrap_transcribe!{
-            { $(#[$attrs])* $($(#[$outer_only_attrs])+)? },
-            { $(#[$attrs])* $(#[$inner_only_attrs])* },
-            $vis, $inner_vis,
-            tuple,
-            $name, $inner_name,
-            { $(<$($lifetimes, )*$($types, )*>)? },
-            { $(<
-                $($lifetimes $(: $lifetime_bounds1 $(+ $lifetime_bounds2)*)?, )*
-                $($types $(:
-                    $($type_bounds1)? $($lifetime_ty_bounds1)?
-                    $(+ $($type_bounds2)? $($lifetime_ty_bounds2)?)*
-                )?, )*
-            >)? },
-            { $(where
-                $(
-                    $($lifetime_wheres)?
-                    $($(for<($for_lt),*>)? $type_wheres)?
-                    :
-                    $($type_bounds3)? $($lifetime_ty_bounds3)?
-                    $(+ $($type_bounds4)? $($lifetime_ty_bounds4)?)*
-                ,)*
-            )? },
-            { $($members)* },
+        ) $($where_clause:tt)*
+    } => {
+        $crate::drop_move_wrap_where!{
+            {
+                { $($options)* },
+                { $($attrs)* },
+                { $($shared_attrs)* $(#[$inner_only_attrs])* },
+                $vis, $inner_vis,
+                tuple,
+                $name, $inner_name,
+                { $($generic_params)* },
+                { $($generic_decls)* },
+                { $($generic_bounds)* },
+            },
+            { { $($members)* }, },
+            $($where_clause)*
         }
     };
 
-    // enum
+    // unit struct, after the generic parameters have been parsed.
     {
-        $(#[$attrs:meta])*
-        $({$(#[$outer_only_attrs:meta])+})?
-        $vis:vis enum $name:ident $(<
-            $($lifetimes:lifetime $(: $lifetime_bounds1:lifetime $(+ $lifetime_bounds2:lifetime)*)?),*
-            $(,)?
-            $($types:ident $(:
-                $($lifetime_ty_bounds1:lifetime)? $($type_bounds1:path)?
-                $(: $($lifetime_ty_bounds2:lifetime)? $($type_bounds2:path)?)*
-            )?),*
-            $(,)?
-        >)?(
+        @body {
+            { $($options:tt)* },
+            { $($attrs:tt)* },
+            { $($shared_attrs:tt)* },
+            $vis:vis, struct, $name:ident,
+        },
+        { $($generic_params:tt)* },
+        { $($generic_decls:tt)* },
+        { $($generic_bounds:tt)* },
+        (
+            $(#[$inner_only_attrs:meta])*
+            $inner_vis:vis $inner_name:ident
+        ) $($where_clause:tt)*
+    } => {
+        $crate::drop_move_wrap_where!{
+            {
+                { $($options)* },
+                { $($attrs)* },
+                { $($shared_attrs)* $(#[$inner_only_attrs])* },
+                $vis, $inner_vis,
+                unit,
+                $name, $inner_name,
+                { $($generic_params)* },
+                { $($generic_decls)* },
+                { $($generic_bounds)* },
+            },
+            { {}, },
+            $($where_clause)*
+        }
+    };
+
+    // enum, after the generic parameters have been parsed.
+    {
+        @body {
+            { $($options:tt)* },
+            { $($attrs:tt)* },
+            { $($shared_attrs:tt)* },
+            $vis:vis, enum, $name:ident,
+        },
+        { $($generic_params:tt)* },
+        { $($generic_decls:tt)* },
+        { $($generic_bounds:tt)* },
+        (
             $(#[$inner_only_attrs:meta])*
             $inner_vis:vis $inner_name:ident {$($members:tt)*}
-        ) $(where
-            $(
-                $($lifetime_wheres:lifetime)?
-                $($(for<($for_lt:lifetime),*>)? $type_wheres:ty)?
-                :
-                $($lifetime_ty_bounds3:lifetime)? $($type_bounds3:path)?
-                $(: $($lifetime_ty_bounds4:lifetime)? $($type_bounds4:path)?)*
-            ),*
-            $(,)?
-        )?;
-    } => {
-        $crate::drop_move_wrap_transcribe!{
-            { $(#[$attrs])* $($(#[$outer_only_attrs])+)? },
-            { $(#[$attrs])* $(#[$inner_only_attrs])* },
-            $vis, $inner_vis,
-            enum,
-            $name, $inner_name,
-            { $(<$($lifetimes, )*$($types, )*>)? },
-            { $(<
-                $($lifetimes $(: $lifetime_bounds1 $(+ $lifetime_bounds2)*)?, )*
-                $($types $(:
-                    $($type_bounds1)? $($lifetime_ty_bounds1)?
-                    $(+ $($type_bounds2)? $($lifetime_ty_bounds2)?)*
-                )?, )*
-            >)? },
-            { $(where
-                $(
-                    $($lifetime_wheres)?
-                    $($(for<($for_lt),*>)? $type_wheres)?
-                    :
-                    $($type_bounds3)? $($lifetime_ty_bounds3)?
-                    $(+ $($type_bounds4)? $($lifetime_ty_bounds4)?)*
-                ,)*
-            )? },
-            { $($members)* },
+        ) $($where_clause:tt)*
+    } => {
+        $crate::drop_move_wrap_where!{
+            {
+                { $($options)* },
+                { $($attrs)* },
+                { $($shared_attrs)* $(#[$inner_only_attrs])* },
+                $vis, $inner_vis,
+                enum,
+                $name, $inner_name,
+                { $($generic_params)* },
+                { $($generic_decls)* },
+                { $($generic_bounds)* },
+            },
+            { { $($members)* }, },
+            $($where_clause)*
         }
     };
 }
 
+/// Parses the generic parameters one at a time. The bounds are parsed by
+/// [`drop_move_wrap_bounds!`], as they may contain `+`, which cannot follow a `path` fragment.
+///
+/// Three lists are built up: the parameters alone, for naming the type; the full declarations of
+/// the parameters; and the declarations without defaults, for `impl` headers.
 #[doc(hidden)]
 #[macro_export]
-macro_rules! drop_move_wrap_transcribe {
+macro_rules! drop_move_wrap_generics {
+    // No generic parameters.
+    { { $($state:tt)* }, ( $($body:tt)* ) $($rest:tt)* } => {
+        $crate::drop_move_wrap_match!{
+            @body { $($state)* }, {}, {<>}, {<>}, ( $($body)* ) $($rest)*
+        }
+    };
+
+    { { $($state:tt)* }, < $($rest:tt)* } => {
+        $crate::drop_move_wrap_generics!{
+            @param { $($state)* }, [], [], [], $($rest)*
+        }
+    };
+
+    // Start of a parameter.
     {
-        { $($attrs:tt)* },
-        { $($inner_attrs:tt)* },
-        $vis:vis, $inner_vis:vis,
-        $decl_kind:ident,
-        $name:ident, $inner_name:ident,
-        { $($generic_params:tt)* },
-        { $($generic_bounds:tt)* },
-        { $($where_clause:tt)* },
-        { $($members:tt)* }$(,)?
+        @param { $($state:tt)* }, [$($params:tt)*], [$($decls:tt)*], [$($bounds:tt)*],
+        > $($rest:tt)*
     } => {
-        $($attrs)*
-        $vis struct $name$($generic_bounds)*(
-            $inner_vis $crate::DropMoveWrapper<$inner_name$($generic_params)*>
-        ) $($where_clause)*;
+        $crate::drop_move_wrap_match!{
+            @body { $($state)* },
+            { <$($params)*> },
+            { <$($decls)*> },
+            { <$($bounds)*> },
+            $($rest)*
+        }
+    };
 
-        $crate::drop_move_wrap_inner_decl!{
-            { $($inner_attrs)* },
-            $inner_vis, $decl_kind,
-            { $inner_name$($generic_bounds)* },
-            { $($where_clause)* },
-            { $($members)* },
+    {
+        @param { $($state:tt)* }, [$($params:tt)*], [$($decls:tt)*], [$($bounds:tt)*],
+        const $param:ident : $type:ty = $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_generics!{
+            @default { $($state)* }, [$($params)*], [$($decls)*], [$($bounds)*],
+            $param, [const $param: $type], [], [],
+            $($rest)*
         }
+    };
 
-        impl$($generic_bounds)* From<$name$($generic_params)*> for $inner_name$($generic_params)*
-        $($where_clause)* {
-            fn from(x: $name$($generic_params)*) -> Self {
-                $crate::DropMoveWrapper::into_inner(x.0)
-            }
+    {
+        @param { $($state:tt)* }, [$($params:tt)*], [$($decls:tt)*], [$($bounds:tt)*],
+        const $param:ident : $type:ty , $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_generics!{
+            @param { $($state)* },
+            [$($params)* $param,],
+            [$($decls)* const $param: $type,],
+            [$($bounds)* const $param: $type,],
+            $($rest)*
         }
+    };
 
-        impl$($generic_bounds)* From<$inner_name$($generic_params)*> for $name$($generic_params)*
-        $($where_clause)* {
-            fn from(x: $inner_name$($generic_params)*) -> Self {
-                Self($crate::DropMoveWrapper::new(x))
-            }
+    {
+        @param { $($state:tt)* }, [$($params:tt)*], [$($decls:tt)*], [$($bounds:tt)*],
+        const $param:ident : $type:ty > $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_generics!{
+            @param { $($state)* },
+            [$($params)* $param,],
+            [$($decls)* const $param: $type,],
+            [$($bounds)* const $param: $type,],
+            > $($rest)*
         }
+    };
 
-        impl$($generic_bounds)* $crate::DropMoveTypes for $inner_name$($generic_params)*
-        $($where_clause)* {
-            type Outer = $name$($generic_params)*;
+    {
+        @param { $($state:tt)* }, [$($params:tt)*], [$($decls:tt)*], [$($bounds:tt)*],
+        $param:lifetime : $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_bounds!{
+            drop_move_wrap_generics,
+            { { $($state)* }, [$($params)*], [$($decls)*], [$($bounds)*], $param },
+            [$param:],
+            $($rest)*
         }
     };
-}
 
-#[doc(hidden)]
-#[macro_export]
-macro_rules! drop_move_wrap_inner_decl {
     {
-        { $($inner_attrs:tt)* },
-        $inner_vis:vis, struct,
-        { $($inner_type:tt)* },
-        { $($where_clause:tt)* },
-        { $($members:tt)* },
+        @param { $($state:tt)* }, [$($params:tt)*], [$($decls:tt)*], [$($bounds:tt)*],
+        $param:lifetime $($rest:tt)*
     } => {
-        $($inner_attrs)*
-        $inner_vis struct $($inner_type)* $($where_clause)* { $($members)* }
+        $crate::drop_move_wrap_generics!{
+            @bounds { { $($state)* }, [$($params)*], [$($decls)*], [$($bounds)*], $param },
+            [$param],
+            $($rest)*
+        }
     };
 
     {
-        { $($inner_attrs:tt)* },
-        $inner_vis:vis, tuple,
-        { $($inner_type:tt)* },
-        { $($where_clause:tt)* },
-        { $($members:tt)* },
+        @param { $($state:tt)* }, [$($params:tt)*], [$($decls:tt)*], [$($bounds:tt)*],
+        $param:ident : $($rest:tt)*
     } => {
-        $($inner_attrs)*
-        $inner_vis struct $($inner_type)* ( $($members)* ) $($where_clause)*;
+        $crate::drop_move_wrap_bounds!{
+            drop_move_wrap_generics,
+            { { $($state)* }, [$($params)*], [$($decls)*], [$($bounds)*], $param },
+            [$param:],
+            $($rest)*
+        }
+    };
+
+    {
+        @param { $($state:tt)* }, [$($params:tt)*], [$($decls:tt)*], [$($bounds:tt)*],
+        $param:ident $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_generics!{
+            @bounds { { $($state)* }, [$($params)*], [$($decls)*], [$($bounds)*], $param },
+            [$param],
+            $($rest)*
+        }
+    };
+
+    // End of a parameter, after its bounds.
+    {
+        @bounds {
+            { $($state:tt)* }, [$($params:tt)*], [$($decls:tt)*], [$($bounds:tt)*], $param:tt
+        },
+        [$($cur:tt)*],
+        , $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_generics!{
+            @param { $($state)* },
+            [$($params)* $param,],
+            [$($decls)* $($cur)*,],
+            [$($bounds)* $($cur)*,],
+            $($rest)*
+        }
+    };
+
+    {
+        @bounds {
+            { $($state:tt)* }, [$($params:tt)*], [$($decls:tt)*], [$($bounds:tt)*], $param:tt
+        },
+        [$($cur:tt)*],
+        > $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_generics!{
+            @param { $($state)* },
+            [$($params)* $param,],
+            [$($decls)* $($cur)*,],
+            [$($bounds)* $($cur)*,],
+            > $($rest)*
+        }
+    };
+
+    {
+        @bounds {
+            { $($state:tt)* }, [$($params:tt)*], [$($decls:tt)*], [$($bounds:tt)*], $param:tt
+        },
+        [$($cur:tt)*],
+        = $default:ty , $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_generics!{
+            @param { $($state)* },
+            [$($params)* $param,],
+            [$($decls)* $($cur)* = $default,],
+            [$($bounds)* $($cur)*,],
+            $($rest)*
+        }
+    };
+
+    {
+        @bounds {
+            { $($state:tt)* }, [$($params:tt)*], [$($decls:tt)*], [$($bounds:tt)*], $param:tt
+        },
+        [$($cur:tt)*],
+        = $default:ty > $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_generics!{
+            @param { $($state)* },
+            [$($params)* $param,],
+            [$($decls)* $($cur)* = $default,],
+            [$($bounds)* $($cur)*,],
+            > $($rest)*
+        }
+    };
+
+    // Default value of a const parameter, which is left out of the `impl` headers. It may be an
+    // expression, so it is parsed one token at a time, tracking the depth of nested `<>`.
+    {
+        @default { $($state:tt)* }, [$($params:tt)*], [$($decls:tt)*], [$($bounds:tt)*],
+        $param:tt, [$($cur:tt)*], [$($default:tt)*], [],
+        , $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_generics!{
+            @param { $($state)* },
+            [$($params)* $param,],
+            [$($decls)* $($cur)* = $($default)*,],
+            [$($bounds)* $($cur)*,],
+            $($rest)*
+        }
+    };
+
+    {
+        @default { $($state:tt)* }, [$($params:tt)*], [$($decls:tt)*], [$($bounds:tt)*],
+        $param:tt, [$($cur:tt)*], [$($default:tt)*], [],
+        > $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_generics!{
+            @param { $($state)* },
+            [$($params)* $param,],
+            [$($decls)* $($cur)* = $($default)*,],
+            [$($bounds)* $($cur)*,],
+            > $($rest)*
+        }
+    };
+
+    {
+        @default { $($state:tt)* }, [$($params:tt)*], [$($decls:tt)*], [$($bounds:tt)*],
+        $param:tt, [$($cur:tt)*], [$($default:tt)*], [$($depth:tt)*],
+        < $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_generics!{
+            @default { $($state)* }, [$($params)*], [$($decls)*], [$($bounds)*],
+            $param, [$($cur)*], [$($default)* <], [< $($depth)*],
+            $($rest)*
+        }
+    };
+
+    {
+        @default { $($state:tt)* }, [$($params:tt)*], [$($decls:tt)*], [$($bounds:tt)*],
+        $param:tt, [$($cur:tt)*], [$($default:tt)*], [< $($depth:tt)*],
+        > $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_generics!{
+            @default { $($state)* }, [$($params)*], [$($decls)*], [$($bounds)*],
+            $param, [$($cur)*], [$($default)* >], [$($depth)*],
+            $($rest)*
+        }
+    };
+
+    {
+        @default { $($state:tt)* }, [$($params:tt)*], [$($decls:tt)*], [$($bounds:tt)*],
+        $param:tt, [$($cur:tt)*], [$($default:tt)*], [$($depth:tt)*],
+        << $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_generics!{
+            @default { $($state)* }, [$($params)*], [$($decls)*], [$($bounds)*],
+            $param, [$($cur)*], [$($default)*], [$($depth)*],
+            < < $($rest)*
+        }
+    };
+
+    {
+        @default { $($state:tt)* }, [$($params:tt)*], [$($decls:tt)*], [$($bounds:tt)*],
+        $param:tt, [$($cur:tt)*], [$($default:tt)*], [$($depth:tt)*],
+        >> $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_generics!{
+            @default { $($state)* }, [$($params)*], [$($decls)*], [$($bounds)*],
+            $param, [$($cur)*], [$($default)*], [$($depth)*],
+            > > $($rest)*
+        }
+    };
+
+    {
+        @default { $($state:tt)* }, [$($params:tt)*], [$($decls:tt)*], [$($bounds:tt)*],
+        $param:tt, [$($cur:tt)*], [$($default:tt)*], [$($depth:tt)*],
+        $token:tt $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_generics!{
+            @default { $($state)* }, [$($params)*], [$($decls)*], [$($bounds)*],
+            $param, [$($cur)*], [$($default)* $token], [$($depth)*],
+            $($rest)*
+        }
+    };
+}
+
+/// Parses the where clause one predicate at a time. The bounds of each predicate are parsed by
+/// [`drop_move_wrap_bounds!`].
+#[doc(hidden)]
+#[macro_export]
+macro_rules! drop_move_wrap_where {
+    { { $($before:tt)* }, { $($after:tt)* }, ; } => {
+        $crate::drop_move_wrap_options!{ $($before)* {}, $($after)* }
+    };
+
+    { { $($before:tt)* }, { $($after:tt)* }, where $($rest:tt)* } => {
+        $crate::drop_move_wrap_where!{
+            @pred { $($before)* }, { $($after)* }, [], $($rest)*
+        }
+    };
+
+    // Start of a predicate.
+    { @pred { $($before:tt)* }, { $($after:tt)* }, [$($preds:tt)*], ; } => {
+        $crate::drop_move_wrap_options!{ $($before)* { where $($preds)* }, $($after)* }
+    };
+
+    {
+        @pred { $($before:tt)* }, { $($after:tt)* }, [$($preds:tt)*],
+        for < $($lifetimes:lifetime),* > $type:ty : $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_bounds!{
+            drop_move_wrap_where,
+            { { $($before)* }, { $($after)* }, [$($preds)*] },
+            [for<$($lifetimes),*> $type:],
+            $($rest)*
+        }
+    };
+
+    {
+        @pred { $($before:tt)* }, { $($after:tt)* }, [$($preds:tt)*],
+        $lifetime:lifetime : $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_bounds!{
+            drop_move_wrap_where,
+            { { $($before)* }, { $($after)* }, [$($preds)*] },
+            [$lifetime:],
+            $($rest)*
+        }
+    };
+
+    {
+        @pred { $($before:tt)* }, { $($after:tt)* }, [$($preds:tt)*],
+        $type:ty : $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_bounds!{
+            drop_move_wrap_where,
+            { { $($before)* }, { $($after)* }, [$($preds)*] },
+            [$type:],
+            $($rest)*
+        }
+    };
+
+    // End of a predicate, after its bounds.
+    {
+        @bounds { { $($before:tt)* }, { $($after:tt)* }, [$($preds:tt)*] }, [$($cur:tt)*],
+        , $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_where!{
+            @pred { $($before)* }, { $($after)* }, [$($preds)* $($cur)*,], $($rest)*
+        }
+    };
+
+    {
+        @bounds { { $($before:tt)* }, { $($after:tt)* }, [$($preds:tt)*] }, [$($cur:tt)*],
+        ;
+    } => {
+        $crate::drop_move_wrap_options!{
+            $($before)* { where $($preds)* $($cur)* }, $($after)*
+        }
+    };
+}
+
+/// Parses a list of bounds, appending them to `[$($cur)*]`, then passes what remains after them
+/// back to `$callback` as `@bounds { $($state)* }, [$($cur)*], $($rest)*`. The list ends at the
+/// first top level `,`, `;`, `=`, or `>`. For backwards compatibility, `:` may be used instead of
+/// `+` to separate bounds.
+///
+/// The common kinds of bound are each parsed in a single step, so that long lists of bounds do
+/// not reach the recursion limit. Anything else is parsed one token at a time, with the depth of
+/// nested `<>` tracked so that only top level separators are interpreted.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! drop_move_wrap_bounds {
+    // One token at a time. The depth is stored as a list of `<`.
+    {
+        @token $callback:ident, { $($state:tt)* }, [$($cur:tt)*], [],
+        + $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_bounds!{ $callback, { $($state)* }, [$($cur)* +], $($rest)* }
+    };
+
+    {
+        @token $callback:ident, { $($state:tt)* }, [$($cur:tt)*], [],
+        : $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_bounds!{ $callback, { $($state)* }, [$($cur)* +], $($rest)* }
+    };
+
+    {
+        @token $callback:ident, { $($state:tt)* }, [$($cur:tt)*], [],
+        , $($rest:tt)*
+    } => {
+        $crate::$callback!{ @bounds { $($state)* }, [$($cur)*], , $($rest)* }
+    };
+
+    {
+        @token $callback:ident, { $($state:tt)* }, [$($cur:tt)*], [],
+        ; $($rest:tt)*
+    } => {
+        $crate::$callback!{ @bounds { $($state)* }, [$($cur)*], ; $($rest)* }
+    };
+
+    {
+        @token $callback:ident, { $($state:tt)* }, [$($cur:tt)*], [],
+        = $($rest:tt)*
+    } => {
+        $crate::$callback!{ @bounds { $($state)* }, [$($cur)*], = $($rest)* }
+    };
+
+    {
+        @token $callback:ident, { $($state:tt)* }, [$($cur:tt)*], [],
+        > $($rest:tt)*
+    } => {
+        $crate::$callback!{ @bounds { $($state)* }, [$($cur)*], > $($rest)* }
+    };
+
+    {
+        @token $callback:ident, { $($state:tt)* }, [$($cur:tt)*], [$($depth:tt)*],
+        < $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_bounds!{
+            @token $callback, { $($state)* }, [$($cur)* <], [< $($depth)*], $($rest)*
+        }
+    };
+
+    {
+        @token $callback:ident, { $($state:tt)* }, [$($cur:tt)*], [< $($depth:tt)*],
+        > $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_bounds!{
+            @token $callback, { $($state)* }, [$($cur)* >], [$($depth)*], $($rest)*
+        }
+    };
+
+    {
+        @token $callback:ident, { $($state:tt)* }, [$($cur:tt)*], [$($depth:tt)*],
+        << $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_bounds!{
+            @token $callback, { $($state)* }, [$($cur)*], [$($depth)*], < < $($rest)*
+        }
+    };
+
+    {
+        @token $callback:ident, { $($state:tt)* }, [$($cur:tt)*], [$($depth:tt)*],
+        >> $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_bounds!{
+            @token $callback, { $($state)* }, [$($cur)*], [$($depth)*], > > $($rest)*
+        }
+    };
+
+    {
+        @token $callback:ident, { $($state:tt)* }, [$($cur:tt)*], [$($depth:tt)*],
+        $token:tt $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_bounds!{
+            @token $callback, { $($state)* }, [$($cur)* $token], [$($depth)*], $($rest)*
+        }
+    };
+
+    // A lifetime.
+    {
+        $callback:ident, { $($state:tt)* }, [$($cur:tt)*],
+        $bound:lifetime + $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_bounds!{ $callback, { $($state)* }, [$($cur)* $bound +], $($rest)* }
+    };
+
+    {
+        $callback:ident, { $($state:tt)* }, [$($cur:tt)*],
+        $bound:lifetime : $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_bounds!{ $callback, { $($state)* }, [$($cur)* $bound +], $($rest)* }
+    };
+
+    {
+        $callback:ident, { $($state:tt)* }, [$($cur:tt)*],
+        $bound:lifetime $($rest:tt)*
+    } => {
+        $crate::$callback!{ @bounds { $($state)* }, [$($cur)* $bound], $($rest)* }
+    };
+
+    // A `?` modifier, as in `?Sized`, or a higher-ranked `for<...>`.
+    {
+        $callback:ident, { $($state:tt)* }, [$($cur:tt)*],
+        ? $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_bounds!{ $callback, { $($state)* }, [$($cur)* ?], $($rest)* }
+    };
+
+    {
+        $callback:ident, { $($state:tt)* }, [$($cur:tt)*],
+        for < $($lifetimes:lifetime),* > $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_bounds!{
+            $callback, { $($state)* }, [$($cur)* for<$($lifetimes),*>], $($rest)*
+        }
+    };
+
+    // Any trait, as long as it is not followed by `+`.
+    {
+        $callback:ident, { $($state:tt)* }, [$($cur:tt)*],
+        $bound:path , $($rest:tt)*
+    } => {
+        $crate::$callback!{ @bounds { $($state)* }, [$($cur)* $bound], , $($rest)* }
+    };
+
+    {
+        $callback:ident, { $($state:tt)* }, [$($cur:tt)*],
+        $bound:path ; $($rest:tt)*
+    } => {
+        $crate::$callback!{ @bounds { $($state)* }, [$($cur)* $bound], ; $($rest)* }
+    };
+
+    {
+        $callback:ident, { $($state:tt)* }, [$($cur:tt)*],
+        $bound:path = $($rest:tt)*
+    } => {
+        $crate::$callback!{ @bounds { $($state)* }, [$($cur)* $bound], = $($rest)* }
+    };
+
+    {
+        $callback:ident, { $($state:tt)* }, [$($cur:tt)*],
+        $bound:path > $($rest:tt)*
+    } => {
+        $crate::$callback!{ @bounds { $($state)* }, [$($cur)* $bound], > $($rest)* }
+    };
+
+    {
+        $callback:ident, { $($state:tt)* }, [$($cur:tt)*],
+        $bound:path : $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_bounds!{ $callback, { $($state)* }, [$($cur)* $bound +], $($rest)* }
+    };
+
+    // A trait followed by `+`, which must be simple enough to match without a `path` fragment.
+    {
+        $callback:ident, { $($state:tt)* }, [$($cur:tt)*],
+        $($segment:ident)::+ + $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_bounds!{
+            $callback, { $($state)* }, [$($cur)* $($segment)::+ +], $($rest)*
+        }
+    };
+
+    {
+        $callback:ident, { $($state:tt)* }, [$($cur:tt)*],
+        $($segment:ident)::+ < $($arg:tt),+ > + $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_bounds!{
+            $callback, { $($state)* }, [$($cur)* $($segment)::+ <$($arg),+> +], $($rest)*
+        }
+    };
+
+    // Anything else.
+    {
+        $callback:ident, { $($state:tt)* }, [$($cur:tt)*],
+        $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_bounds!{
+            @token $callback, { $($state)* }, [$($cur)*], [], $($rest)*
+        }
+    };
+}
+
+/// Parses the comma separated options from `#[drop_move(...)]` attributes. Options either change
+/// the wrapper type, or add extra items, which are generated by [`drop_move_wrap_extra!`].
+#[doc(hidden)]
+#[macro_export]
+macro_rules! drop_move_wrap_options {
+    { { $($options:tt)* }, $($rest:tt)* } => {
+        $crate::drop_move_wrap_options!{
+            @option [$crate::DropMoveWrapper], [], { $($options)* }, $($rest)*
+        }
+    };
+
+    { @option [$($wrapper:tt)*], [$($extras:tt)*], {}, $($rest:tt)* } => {
+        $crate::drop_move_wrap_transcribe!{ { $($wrapper)* }, { $($extras)* }, $($rest)* }
+    };
+
+    {
+        @option [$($wrapper:tt)*], [$($extras:tt)*],
+        { , $($options:tt)* }, $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_options!{
+            @option [$($wrapper)*], [$($extras)*], { $($options)* }, $($rest)*
+        }
+    };
+
+    // The cancel flag would have to be checked when dropping in place, which is not supported.
+    {
+        @option [$krate:tt :: PinDropMoveWrapper], [$($extras:tt)*],
+        { cancelable $($options:tt)* }, $($rest:tt)*
+    } => {
+        compile_error!("the `pin` option cannot be combined with `cancelable`");
+    };
+
+    {
+        @option [$($wrapper:tt)*], [$($extras:tt)*],
+        { cancelable $($options:tt)* }, $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_options!{
+            @option [$crate::CancelableDropMoveWrapper], [$($extras)* [cancelable]],
+            { $($options)* }, $($rest)*
+        }
+    };
+
+    {
+        @option [$($wrapper:tt)*], [$($extras:tt)*],
+        { async_drop $($options:tt)* }, $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_options!{
+            @option [$($wrapper)*], [$($extras)* [async_drop]], { $($options)* }, $($rest)*
+        }
+    };
+
+    {
+        @option [$krate:tt :: CancelableDropMoveWrapper], [$($extras:tt)*],
+        { pin $($options:tt)* }, $($rest:tt)*
+    } => {
+        compile_error!("the `pin` option cannot be combined with `cancelable`");
+    };
+
+    {
+        @option [$($wrapper:tt)*], [$($extras:tt)*],
+        { pin $($options:tt)* }, $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_options!{
+            @option [$crate::PinDropMoveWrapper], [$($extras)* [pin] [pin_fields]],
+            { $($options)* }, $($rest)*
+        }
+    };
+
+    {
+        @option [$($wrapper:tt)*], [$($extras:tt)*],
+        { try_drop $($options:tt)* }, $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_options!{
+            @option [$($wrapper)*], [$($extras)* [try_drop]], { $($options)* }, $($rest)*
+        }
+    };
+
+    {
+        @option [$($wrapper:tt)*], [$($extras:tt)*],
+        { fields $($options:tt)* }, $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_options!{
+            @option [$($wrapper)*], [$($extras)* [fields]], { $($options)* }, $($rest)*
+        }
+    };
+
+    {
+        @option [$($wrapper:tt)*], [$($extras:tt)*],
+        { must_consume ( $action:ident ) $($options:tt)* }, $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_options!{
+            @option [$($wrapper)*], [$($extras)* [must_consume $action]], { $($options)* },
+            $($rest)*
+        }
+    };
+
+    {
+        @option [$($wrapper:tt)*], [$($extras:tt)*],
+        { must_consume $($options:tt)* }, $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_options!{
+            @option [$($wrapper)*], [$($extras)* [must_consume Panic]], { $($options)* },
+            $($rest)*
+        }
+    };
+
+    {
+        @option [$($wrapper:tt)*], [$($extras:tt)*],
+        { tracked ( $field:tt ) $($options:tt)* }, $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_options!{
+            @option [$($wrapper)*], [$($extras)* [tracked $field]], { $($options)* }, $($rest)*
+        }
+    };
+
+    {
+        @option [$($wrapper:tt)*], [$($extras:tt)*],
+        { variants ( $handle:ident ) $($options:tt)* }, $($rest:tt)*
+    } => {
+        $crate::drop_move_wrap_options!{
+            @option [$($wrapper)*], [$($extras)* [variants $handle]], { $($options)* },
+            $($rest)*
+        }
+    };
+
+    {
+        @option [$($wrapper:tt)*], [$($extras:tt)*],
+        { $option:tt $($options:tt)* }, $($rest:tt)*
+    } => {
+        compile_error!(concat!("unknown drop_move option `", stringify!($option), "`"));
+    };
+}
+
+/// Generates the extra items for each option, one at a time.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! drop_move_wrap_extras {
+    { {}, $($context:tt)* } => {};
+
+    { { $extra:tt $($extras:tt)* }, $($context:tt)* } => {
+        $crate::drop_move_wrap_extra!{ $extra, $($context)* }
+        $crate::drop_move_wrap_extras!{ { $($extras)* }, $($context)* }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! drop_move_wrap_extra {
+    {
+        [variants $handle:ident],
+        $vis:vis, $inner_vis:vis,
+        enum,
+        $name:ident, $inner_name:ident,
+        { $($generic_params:tt)* },
+        { $($generic_bounds:tt)* },
+        { $($where_clause:tt)* },
+        { $($members:tt)* }$(,)?
+    } => {
+        $crate::drop_move_wrap_variants!{
+            {
+                $vis, $handle, $inner_name, $inner_name$($generic_params)*,
+                { $($generic_params)* },
+                { $($generic_bounds)* },
+                { $($where_clause)* },
+            },
+            [],
+            $($members)*
+        }
+    };
+
+    { [variants $handle:ident], $($context:tt)* } => {
+        compile_error!("the `variants` option requires the inner structure to be an enum");
+    };
+
+    {
+        [fields],
+        $vis:vis, $inner_vis:vis,
+        struct,
+        $name:ident, $inner_name:ident,
+        { $($generic_params:tt)* },
+        { $($generic_bounds:tt)* },
+        { $($where_clause:tt)* },
+        { $($(#[$field_attr:meta])* $field_vis:vis $field:ident : $field_type:ty),* $(,)? }$(,)?
+    } => {
+        const _: () = {
+            #[allow(dead_code, missing_docs)]
+            pub struct PartialFields$($generic_bounds)* $($where_clause)* {
+                $($field_vis $field: ::core::option::Option<$field_type>,)*
+            }
+
+            impl$($generic_bounds)* $crate::DropMoveFields for $inner_name$($generic_params)*
+            $($where_clause)* {
+                type Fields = PartialFields$($generic_params)*;
+
+                fn into_fields(self) -> Self::Fields {
+                    PartialFields {
+                        $($field: ::core::option::Option::Some(self.$field),)*
+                    }
+                }
+            }
+        };
+    };
+
+    // Implemented in `DropMoveTypes`, by `drop_move_wrap_tracked!`.
+    { [tracked $field:tt], $($context:tt)* } => {};
+
+    { [fields], $($context:tt)* } => {
+        compile_error!("the `fields` option requires the inner structure to have named fields");
+    };
+
+    {
+        [pin],
+        $vis:vis, $inner_vis:vis,
+        $decl_kind:ident,
+        $name:ident, $inner_name:ident,
+        { $($generic_params:tt)* },
+        { $($generic_bounds:tt)* },
+        { $($where_clause:tt)* },
+        { $($members:tt)* }$(,)?
+    } => {
+        // Projecting to the inner structure is only sound if the outer structure cannot be made
+        // `Unpin` while the inner structure is not.
+        $crate::drop_move_wrap_unpin!{
+            $name$($generic_params)*,
+            { $($generic_bounds)* },
+            { $($where_clause)* },
+            { $inner_name$($generic_params)* },
+        }
+
+        impl$($generic_bounds)* $name$($generic_params)* $($where_clause)* {
+            /// Project to the pinned inner structure.
+            #[allow(dead_code, unknown_lints, private_interfaces)]
+            $vis fn as_pin_mut(
+                self: ::core::pin::Pin<&mut Self>,
+            ) -> ::core::pin::Pin<&mut $inner_name$($generic_params)*> {
+                let wrapper = unsafe { self.map_unchecked_mut(|x| &mut x.0) };
+                $crate::PinDropMoveWrapper::as_pin_mut(wrapper)
+            }
+        }
+    };
+
+    // Every field is structurally pinned, so the inner structure must not implement Drop, as that
+    // could move out of them.
+    {
+        [pin_fields],
+        $vis:vis, $inner_vis:vis,
+        struct,
+        $name:ident, $inner_name:ident,
+        { $($generic_params:tt)* },
+        { $($generic_bounds:tt)* },
+        { $($where_clause:tt)* },
+        { $($(#[$field_attr:meta])* $field_vis:vis $field:ident : $field_type:ty),* $(,)? }$(,)?
+    } => {
+        const _: () = {
+            #[allow(dead_code)]
+            trait MustNotImplDrop {}
+            #[allow(drop_bounds)]
+            impl<T: Drop> MustNotImplDrop for T {}
+            impl$($generic_bounds)* MustNotImplDrop for $inner_name$($generic_params)*
+            $($where_clause)* {}
+        };
+
+        // Likewise, implementing `Unpin` could allow moving out of pinned fields.
+        $crate::drop_move_wrap_unpin!{
+            $inner_name$($generic_params)*,
+            { $($generic_bounds)* },
+            { $($where_clause)* },
+            { $($field_type),* },
+        }
+
+        impl$($generic_bounds)* $inner_name$($generic_params)* $($where_clause)* {$(
+            /// Project to the pinned field.
+            #[allow(dead_code, unknown_lints, private_interfaces)]
+            $field_vis fn $field(
+                self: ::core::pin::Pin<&mut Self>,
+            ) -> ::core::pin::Pin<&mut $field_type> {
+                unsafe { self.map_unchecked_mut(|x| &mut x.$field) }
+            }
+        )*}
+    };
+
+    { [pin_fields], $($context:tt)* } => {};
+
+    {
+        [cancelable],
+        $vis:vis, $inner_vis:vis,
+        $decl_kind:ident,
+        $name:ident, $inner_name:ident,
+        { $($generic_params:tt)* },
+        { $($generic_bounds:tt)* },
+        { $($where_clause:tt)* },
+        { $($members:tt)* }$(,)?
+    } => {
+        impl$($generic_bounds)* $name$($generic_params)* $($where_clause)* {
+            /// Disarm the structure in place, so that `drop_move` will not be called when it is
+            /// dropped.
+            ///
+            /// This is an associated function so that will not conflict with any methods of the
+            /// inner structure, which are accessible through `Deref`.
+            #[allow(dead_code)]
+            $inner_vis fn cancel(self_: &mut Self) {
+                $crate::CancelableDropMoveWrapper::cancel(&mut self_.0)
+            }
+
+            /// Check whether `cancel` has been called.
+            #[allow(dead_code)]
+            $inner_vis fn is_canceled(self_: &Self) -> bool {
+                $crate::CancelableDropMoveWrapper::is_canceled(&self_.0)
+            }
+        }
+    };
+
+    {
+        [async_drop],
+        $vis:vis, $inner_vis:vis,
+        $decl_kind:ident,
+        $name:ident, $inner_name:ident,
+        { $($generic_params:tt)* },
+        { $($generic_bounds:tt)* },
+        { $($where_clause:tt)* },
+        { $($members:tt)* }$(,)?
+    } => {
+        impl$($generic_bounds)* $crate::DropMove for $inner_name$($generic_params)*
+        $($where_clause)* {
+            fn drop_move(self_: $crate::DropHandle<Self>) {
+                $crate::spawn_drop(self_);
+            }
+        }
+
+        impl$($generic_bounds)* $name$($generic_params)* $($where_clause)* {
+            /// Drop by move, and wait for `drop_move_async` to finish rather than passing it to
+            /// `spawn_drop`.
+            #[allow(dead_code, unknown_lints, private_interfaces)]
+            $vis async fn close(self) {
+                let inner: $inner_name$($generic_params)* = self.into();
+                <$inner_name$($generic_params)* as $crate::AsyncDropMove>::drop_move_async(
+                    inner.into(),
+                )
+                .await
+            }
+        }
+    };
+
+    {
+        [must_consume $action:ident],
+        $vis:vis, $inner_vis:vis,
+        $decl_kind:ident,
+        $name:ident, $inner_name:ident,
+        { $($generic_params:tt)* },
+        { $($generic_bounds:tt)* },
+        { $($where_clause:tt)* },
+        { $($members:tt)* }$(,)?
+    } => {
+        impl$($generic_bounds)* $crate::DropMove for $inner_name$($generic_params)*
+        $($where_clause)* {
+            fn drop_move(self_: $crate::DropHandle<Self>) {
+                <$crate::must_consume::$action as $crate::ConsumeAction>::dropped::<
+                    $name$($generic_params)*
+                >();
+                ::core::mem::drop(self_);
+            }
+        }
+
+        impl$($generic_bounds)* $name$($generic_params)* $($where_clause)* {
+            /// Convert to the inner structure, which is the only way to avoid the action taken when
+            /// this is dropped.
+            #[allow(dead_code, unknown_lints, private_interfaces)]
+            $vis fn consume(self) -> $inner_name$($generic_params)* {
+                self.into()
+            }
+        }
+    };
+
+    {
+        [try_drop],
+        $vis:vis, $inner_vis:vis,
+        $decl_kind:ident,
+        $name:ident, $inner_name:ident,
+        { $($generic_params:tt)* },
+        { $($generic_bounds:tt)* },
+        { $($where_clause:tt)* },
+        { $($members:tt)* }$(,)?
+    } => {
+        impl$($generic_bounds)* $crate::DropMove for $inner_name$($generic_params)*
+        $($where_clause)* {
+            fn drop_move(self_: $crate::DropHandle<Self>) {
+                if let ::core::result::Result::Err(error) =
+                    <Self as $crate::TryDropMove>::try_drop_move(self_)
+                {
+                    <Self as $crate::TryDropMove>::on_drop_error(error);
+                }
+            }
+        }
+
+        impl$($generic_bounds)* $name$($generic_params)* $($where_clause)* {
+            /// Drop by move, returning any error from `try_drop_move` rather than passing it to
+            /// `on_drop_error`.
+            #[allow(dead_code, unknown_lints, private_interfaces)]
+            $vis fn close(
+                self,
+            ) -> ::core::result::Result<
+                (),
+                <$inner_name$($generic_params)* as $crate::TryDropMove>::Error,
+            > {
+                let inner: $inner_name$($generic_params)* = self.into();
+                <$inner_name$($generic_params)* as $crate::TryDropMove>::try_drop_move(inner.into())
+            }
+        }
+    };
+}
+
+/// Implements `Unpin` for `$type` only if all of the `$pinned` types are `Unpin`, which stops any
+/// other implementation from being written. The extra lifetime keeps the bounds from being trivial,
+/// as a trivially false bound would be an error.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! drop_move_wrap_unpin {
+    // No generic parameters, as passed by `#[drop_move]`.
+    { $type:ty, {}, $($rest:tt)* } => {
+        $crate::drop_move_wrap_unpin!{ $type, { <> }, $($rest)* }
+    };
+
+    {
+        $type:ty,
+        { < $($generic_bounds:tt)* },
+        {},
+        { $($pinned:ty),* }$(,)?
+    } => {
+        impl<'__pin, $($generic_bounds)* ::core::marker::Unpin for $type
+        where
+            $($crate::UnpinIf<'__pin, $pinned>: ::core::marker::Unpin,)*
+        {}
+    };
+
+    {
+        $type:ty,
+        { < $($generic_bounds:tt)* },
+        { where $($preds:tt)* },
+        { $($pinned:ty),* }$(,)?
+    } => {
+        impl<'__pin, $($generic_bounds)* ::core::marker::Unpin for $type
+        where
+            $($crate::UnpinIf<'__pin, $pinned>: ::core::marker::Unpin,)*
+            $($preds)*
+        {}
+    };
+}
+
+/// Parses the variants of an enum one at a time, then generates the `variants` handle enum.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! drop_move_wrap_variants {
+    {
+        {
+            $vis:vis, $handle:ident, $inner_name:ident, $inner_type:ty,
+            { $($generic_params:tt)* },
+            { $($generic_bounds:tt)* },
+            { $($where_clause:tt)* },
+        },
+        [$([$variant:ident, ($($field_type:ty),*), $fields:expr])*],
+    } => {
+        /// The variants of a structure being dropped, each holding a `VariantHandle`.
+        #[allow(dead_code, unknown_lints, private_interfaces)]
+        $vis enum $handle$($generic_bounds)* $($where_clause)* {$(
+            #[allow(missing_docs)]
+            $variant($crate::VariantHandle<$inner_type, ($($field_type,)*)>),
+        )*}
+
+        impl$($generic_bounds)* From<$crate::DropHandle<$inner_name$($generic_params)*>>
+        for $handle$($generic_params)* $($where_clause)* {
+            fn from(handle: $crate::DropHandle<$inner_name$($generic_params)*>) -> Self {
+                match &*(*handle).0 {$(
+                    $inner_name::$variant { .. } => {
+                        $handle::$variant($crate::VariantHandle::new(handle, $fields))
+                    }
+                )*}
+            }
+        }
+    };
+
+    // Tuple variant.
+    {
+        $context:tt, [$($done:tt)*],
+        $(#[$attr:meta])* $variant:ident ( $($(#[$field_attr:meta])* $field_type:ty),* $(,)? )
+        $(= $discriminant:expr)? $(, $($rest:tt)*)?
+    } => {
+        $crate::drop_move_wrap_variants!{
+            $context,
+            [
+                $($done)*
+                [
+                    $variant, ($($field_type),*),
+                    $crate::drop_move_wrap_variants!{
+                        @tuple $context, $variant, [], [$($field_type),*],
+                        [x0 x1 x2 x3 x4 x5 x6 x7 x8 x9 x10 x11 x12 x13 x14 x15]
+                    }
+                ]
+            ],
+            $($($rest)*)?
+        }
+    };
+
+    // Struct variant.
+    {
+        $context:tt, [$($done:tt)*],
+        $(#[$attr:meta])* $variant:ident {
+            $($(#[$field_attr:meta])* $field:ident : $field_type:ty),* $(,)?
+        }
+        $(= $discriminant:expr)? $(, $($rest:tt)*)?
+    } => {
+        $crate::drop_move_wrap_variants!{
+            $context,
+            [
+                $($done)*
+                [
+                    $variant, ($($field_type),*),
+                    $crate::drop_move_wrap_variants!{ @fields $context, $variant, [$($field)*] }
+                ]
+            ],
+            $($($rest)*)?
+        }
+    };
+
+    // Unit variant.
+    {
+        $context:tt, [$($done:tt)*],
+        $(#[$attr:meta])* $variant:ident
+        $(= $discriminant:expr)? $(, $($rest:tt)*)?
+    } => {
+        $crate::drop_move_wrap_variants!{
+            $context,
+            [
+                $($done)*
+                [$variant, (), $crate::drop_move_wrap_variants!{ @fields $context, $variant, [] }]
+            ],
+            $($($rest)*)?
+        }
+    };
+
+    // Name the fields of a tuple variant.
+    {
+        @tuple $context:tt, $variant:ident, [$($field:ident)*], [],
+        [$($unused:ident)*]
+    } => {
+        $crate::drop_move_wrap_variants!{ @tuple_fields $context, $variant, [$($field)*] }
+    };
+
+    {
+        @tuple $context:tt, $variant:ident, [$($field:ident)*], [$field_type:ty $(, $rest:ty)*],
+        [$next:ident $($unused:ident)*]
+    } => {
+        $crate::drop_move_wrap_variants!{
+            @tuple $context, $variant, [$($field)* $next], [$($rest),*], [$($unused)*]
+        }
+    };
+
+    {
+        @tuple $context:tt, $variant:ident, [$($field:ident)*], [$($field_type:ty),+], []
+    } => {
+        compile_error!("the `variants` option supports tuple variants with at most 16 fields")
+    };
+
+    // The function to extract the fields of a variant.
+    {
+        @tuple_fields { $vis:vis, $handle:ident, $inner_name:ident, $($context:tt)* },
+        $variant:ident, [$($field:ident)*]
+    } => {
+        |inner| match inner {
+            $inner_name::$variant($($field),*) => ($($field,)*),
+            #[allow(unreachable_patterns)]
+            _ => unreachable!(),
+        }
+    };
+
+    {
+        @fields { $vis:vis, $handle:ident, $inner_name:ident, $($context:tt)* },
+        $variant:ident, [$($field:ident)*]
+    } => {
+        |inner| match inner {
+            $inner_name::$variant { $($field),* } => ($($field,)*),
+            #[allow(unreachable_patterns)]
+            _ => unreachable!(),
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! drop_move_wrap_transcribe {
+    {
+        { $($wrapper:tt)* },
+        { $($extras:tt)* },
+        { $($attrs:tt)* },
+        { $($inner_attrs:tt)* },
+        $vis:vis, $inner_vis:vis,
+        $decl_kind:ident,
+        $name:ident, $inner_name:ident,
+        { $($generic_params:tt)* },
+        { $($generic_decls:tt)* },
+        { $($generic_bounds:tt)* },
+        { $($where_clause:tt)* },
+        { $($members:tt)* }$(,)?
+    } => {
+        $($attrs)*
+        #[repr(transparent)]
+        $vis struct $name$($generic_decls)*(
+            $inner_vis $($wrapper)*<$inner_name$($generic_params)*>
+        ) $($where_clause)*;
+
+        $crate::drop_move_wrap_inner_decl!{
+            { $($inner_attrs)* },
+            $inner_vis, $decl_kind,
+            { $inner_name$($generic_decls)* },
+            { $($where_clause)* },
+            { $($members)* },
+        }
+
+        impl$($generic_bounds)* From<$name$($generic_params)*> for $inner_name$($generic_params)*
+        $($where_clause)* {
+            fn from(x: $name$($generic_params)*) -> Self {
+                $($wrapper)*::into_inner(x.0)
+            }
+        }
+
+        impl$($generic_bounds)* From<$inner_name$($generic_params)*> for $name$($generic_params)*
+        $($where_clause)* {
+            fn from(x: $inner_name$($generic_params)*) -> Self {
+                Self::new_from_inner(x)
+            }
+        }
+
+        impl$($generic_bounds)* $crate::DropMoveTypes for $inner_name$($generic_params)*
+        $($where_clause)* {
+            type Outer = $name$($generic_params)*;
+
+            $crate::drop_move_wrap_tracked!{ $($extras)* }
+        }
+
+        impl$($generic_bounds)* $name$($generic_params)* $($where_clause)* {
+            /// Wrap the inner structure. This is the same as [`From`], but can be used in `const`
+            /// contexts.
+            #[allow(dead_code)]
+            $inner_vis const fn new_from_inner(inner: $inner_name$($generic_params)*) -> Self {
+                Self($($wrapper)*::new(inner))
+            }
+
+            /// Disarm the structure, so that `drop_move` will not be called, and return the inner
+            /// structure. This is the same as [`Into`].
+            ///
+            /// This is an associated function so that will not conflict with any methods of the
+            /// inner structure, which are accessible through `Deref`.
+            #[allow(dead_code)]
+            $inner_vis fn defuse(self_: Self) -> $inner_name$($generic_params)* {
+                $($wrapper)*::defuse(self_.0)
+            }
+        }
+
+        $crate::drop_move_wrap_layout!{
+            { $($wrapper)* },
+            $name, $inner_name,
+            { $($generic_params)* },
+        }
+
+        $crate::drop_move_wrap_unit!{
+            $vis, $decl_kind,
+            $name, $inner_name,
+            { $($generic_params)* },
+            { $($generic_bounds)* },
+            { $($where_clause)* },
+        }
+
+        $crate::drop_move_wrap_extras!{
+            { $($extras)* },
+            $vis, $inner_vis,
+            $decl_kind,
+            $name, $inner_name,
+            { $($generic_params)* },
+            { $($generic_bounds)* },
+            { $($where_clause)* },
+            { $($members)* },
+        }
+    };
+}
+
+/// Asserts that the outer structure has the same size and alignment as the inner structure. This is
+/// skipped for generic structures, whose layout is only known once they are instantiated, and for
+/// the cancelable wrapper, which also stores a flag.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! drop_move_wrap_layout {
+    { { $krate:tt :: CancelableDropMoveWrapper }, $($rest:tt)* } => {};
+
+    { { $($wrapper:tt)* }, $name:ident, $inner_name:ident, {}$(,)? } => {
+        const _: () = ::core::assert!(
+            ::core::mem::size_of::<$name>() == ::core::mem::size_of::<$inner_name>()
+                && ::core::mem::align_of::<$name>() == ::core::mem::align_of::<$inner_name>(),
+            "the outer structure must have the same layout as the inner structure"
+        );
+    };
+
+    { { $($wrapper:tt)* }, $name:ident, $inner_name:ident, { $($generic_params:tt)* }$(,)? } => {};
+}
+
+/// Implements `DropMoveTypes::on_drop_move` if the `tracked` option was given, to record calls to
+/// `drop_move` with the tracked field.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! drop_move_wrap_tracked {
+    {} => {};
+
+    { [tracked $field:tt] $($extras:tt)* } => {
+        fn on_drop_move(outer: &Self::Outer) {
+            $crate::testing::Tracked::mark_drop_move(&(*outer.0).$field);
+        }
+    };
+
+    { $extra:tt $($extras:tt)* } => {
+        $crate::drop_move_wrap_tracked!{ $($extras)* }
+    };
+}
+
+/// Generates the constructor for a unit inner structure, which can be created from nothing.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! drop_move_wrap_unit {
+    {
+        $vis:vis, unit,
+        $name:ident, $inner_name:ident,
+        { $($generic_params:tt)* },
+        { $($generic_bounds:tt)* },
+        { $($where_clause:tt)* },
+    } => {
+        impl$($generic_bounds)* $name$($generic_params)* $($where_clause)* {
+            /// Create the structure, which will be dropped with `drop_move`.
+            #[allow(dead_code, unknown_lints, private_interfaces)]
+            $vis const fn new() -> Self {
+                Self::new_from_inner($inner_name)
+            }
+        }
+    };
+
+    { $vis:vis, $decl_kind:ident, $($rest:tt)* } => {};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! drop_move_wrap_inner_decl {
+    {
+        { $($inner_attrs:tt)* },
+        $inner_vis:vis, struct,
+        { $($inner_type:tt)* },
+        { $($where_clause:tt)* },
+        { $($members:tt)* },
+    } => {
+        $($inner_attrs)*
+        $inner_vis struct $($inner_type)* $($where_clause)* { $($members)* }
+    };
+
+    {
+        { $($inner_attrs:tt)* },
+        $inner_vis:vis, tuple,
+        { $($inner_type:tt)* },
+        { $($where_clause:tt)* },
+        { $($members:tt)* },
+    } => {
+        $($inner_attrs)*
+        $inner_vis struct $($inner_type)* ( $($members)* ) $($where_clause)*;
+    };
+
+    {
+        { $($inner_attrs:tt)* },
+        $inner_vis:vis, unit,
+        { $($inner_type:tt)* },
+        { $($where_clause:tt)* },
+        {},
+    } => {
+        $($inner_attrs)*
+        $inner_vis struct $($inner_type)* $($where_clause)*;
     };
 
     {
@@ -0,0 +1,273 @@
+use super::*;
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+#[cfg(feature = "alloc")]
+drop_move_wrap! {
+    /// A stack of cleanup actions, which are run in the reverse of the order they were pushed when
+    /// it is dropped.
+    ///
+    /// Each action is held in a [`BoxDropGuard`]. If one of them panics, the rest are still run
+    /// while unwinding, although a second panic will abort. Use [`commit`](Self::commit) to
+    /// disarm all of the actions, e.g. once a multi step setup has succeeded and there is nothing
+    /// left to undo.
+    ///
+    /// ```
+    /// use drop_move::DropStack;
+    /// use std::cell::RefCell;
+    ///
+    /// let log = RefCell::new(Vec::new());
+    /// {
+    ///     let mut undo = DropStack::new();
+    ///     undo.push(|| log.borrow_mut().push("close file"));
+    ///     undo.push(|| log.borrow_mut().push("remove directory"));
+    ///     // Something fails, so the steps are undone.
+    /// }
+    /// assert_eq!(*log.borrow(), ["remove directory", "close file"]);
+    ///
+    /// let mut undo = DropStack::new();
+    /// undo.push(|| log.borrow_mut().push("unreachable"));
+    /// undo.commit();
+    /// assert_eq!(log.borrow().len(), 2);
+    /// ```
+    ///
+    /// Requires the `alloc` feature.
+    pub struct DropStack<'a>(DropStackInner {
+        guards: Vec<BoxDropGuard<'a>>,
+    });
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> DropMove for DropStackInner<'a> {
+    fn drop_move(self_: DropHandle<Self>) {
+        let mut guards = DropHandle::into_inner(self_).guards;
+
+        // A Vec drops its elements from first to last, and keeps dropping the rest if one panics.
+        guards.reverse();
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> DropStack<'a> {
+    /// Create an empty stack.
+    pub fn new() -> Self {
+        Self::from(DropStackInner { guards: Vec::new() })
+    }
+
+    /// Add an action to the top of the stack.
+    pub fn push(&mut self, f: impl FnOnce() + 'a) {
+        self.0.guards.push(DropGuard::boxed(f));
+    }
+
+    /// Remove the action at the top of the stack. Dropping the returned guard runs the action,
+    /// while [`DropGuard::into_inner`] removes it without running it.
+    pub fn pop(&mut self) -> Option<BoxDropGuard<'a>> {
+        self.0.guards.pop()
+    }
+
+    /// The number of actions on the stack.
+    pub fn len(&self) -> usize {
+        self.0.guards.len()
+    }
+
+    /// Check whether there are no actions on the stack.
+    pub fn is_empty(&self) -> bool {
+        self.0.guards.is_empty()
+    }
+
+    /// Disarm the stack, dropping every action without running it.
+    pub fn commit(self) {
+        let guards = DropStackInner::from(self).guards;
+        guards
+            .into_iter()
+            .for_each(|guard| mem::drop(guard.into_inner()));
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> Default for DropStack<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+drop_move_wrap! {
+    /// A `DropStack` with a fixed capacity of `N`, which does not need the `alloc` feature.
+    ///
+    /// Without boxing, every action must have the same type `F`, e.g. a function pointer.
+    ///
+    /// ```
+    /// use drop_move::ArrayDropStack;
+    /// use std::sync::atomic::{AtomicU32, Ordering};
+    ///
+    /// static STEPS: AtomicU32 = AtomicU32::new(1);
+    ///
+    /// let mut undo = ArrayDropStack::<fn(), 2>::new();
+    /// assert!(undo.push(|| { STEPS.fetch_add(1, Ordering::Relaxed); }).is_ok());
+    /// assert!(undo.push(|| { STEPS.fetch_sub(1, Ordering::Relaxed); }).is_ok());
+    /// assert!(undo.push(|| {}).is_err());
+    /// drop(undo.pop().unwrap().into_inner());
+    /// drop(undo);
+    /// assert_eq!(STEPS.load(Ordering::Relaxed), 2);
+    /// ```
+    pub struct ArrayDropStack<F: FnOnce(), const N: usize>(ArrayDropStackInner {
+        guards: [Option<DropGuard<F>>; N],
+        len: usize,
+    });
+}
+
+impl<F: FnOnce(), const N: usize> DropMove for ArrayDropStackInner<F, N> {
+    fn drop_move(self_: DropHandle<Self>) {
+        let mut inner = DropHandle::into_inner(self_);
+
+        // Arrays are dropped the same way as a Vec.
+        inner.guards[..inner.len].reverse();
+    }
+}
+
+impl<F: FnOnce(), const N: usize> ArrayDropStack<F, N> {
+    /// Create an empty stack.
+    pub fn new() -> Self {
+        Self::from(ArrayDropStackInner {
+            guards: core::array::from_fn(|_| None),
+            len: 0,
+        })
+    }
+
+    /// Add an action to the top of the stack, or give it back if the stack is full.
+    pub fn push(&mut self, f: F) -> Result<(), F> {
+        let inner = &mut *self.0;
+        match inner.guards.get_mut(inner.len) {
+            Some(slot) => {
+                *slot = Some(DropGuard::new(f));
+                inner.len += 1;
+                Ok(())
+            }
+            None => Err(f),
+        }
+    }
+
+    /// Remove the action at the top of the stack. Dropping the returned guard runs the action,
+    /// while [`DropGuard::into_inner`] removes it without running it.
+    pub fn pop(&mut self) -> Option<DropGuard<F>> {
+        let inner = &mut *self.0;
+        inner.len = inner.len.checked_sub(1)?;
+        inner.guards[inner.len].take()
+    }
+
+    /// The number of actions on the stack.
+    pub fn len(&self) -> usize {
+        self.0.len
+    }
+
+    /// Check whether there are no actions on the stack.
+    pub fn is_empty(&self) -> bool {
+        self.0.len == 0
+    }
+
+    /// Disarm the stack, dropping every action without running it.
+    pub fn commit(self) {
+        let guards = ArrayDropStackInner::from(self).guards;
+        IntoIterator::into_iter(guards)
+            .flatten()
+            .for_each(|guard| mem::drop(guard.into_inner()));
+    }
+}
+
+impl<F: FnOnce(), const N: usize> Default for ArrayDropStack<F, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    extern crate std;
+    use core::cell::RefCell;
+    use std::vec::Vec;
+
+    #[test]
+    fn array() {
+        let log = RefCell::new(Vec::new());
+        let log_ref = &log;
+        let push = |x| move || log_ref.borrow_mut().push(x);
+        {
+            let mut stack = ArrayDropStack::<_, 3>::new();
+            assert!(stack.is_empty());
+            assert!(stack.push(push(1)).is_ok());
+            assert!(stack.push(push(2)).is_ok());
+            assert!(stack.push(push(3)).is_ok());
+            assert!(stack.push(push(4)).is_err());
+            assert_eq!(stack.len(), 3);
+
+            mem::drop(stack.pop());
+            let _disarmed = stack.pop().unwrap().into_inner();
+            assert!(stack.push(push(5)).is_ok());
+        }
+        assert_eq!(*log.borrow(), [3, 5, 1]);
+
+        let mut stack = ArrayDropStack::<_, 2>::default();
+        assert!(stack.push(push(6)).is_ok());
+        stack.commit();
+        assert_eq!(*log.borrow(), [3, 5, 1]);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn stack() {
+        let log = RefCell::new(Vec::new());
+        {
+            let mut stack = DropStack::new();
+            for i in 0..4 {
+                let log = &log;
+                stack.push(move || log.borrow_mut().push(i));
+            }
+            assert_eq!(stack.len(), 4);
+
+            mem::drop(stack.pop());
+            mem::drop(stack.pop().unwrap().into_inner());
+        }
+        assert_eq!(*log.borrow(), [3, 1, 0]);
+
+        let mut stack = DropStack::default();
+        stack.push(|| log.borrow_mut().push(4));
+        stack.commit();
+        assert_eq!(*log.borrow(), [3, 1, 0]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn panic() {
+        use std::panic::{self, AssertUnwindSafe};
+
+        let log = RefCell::new(Vec::new());
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut stack = DropStack::new();
+            stack.push(|| log.borrow_mut().push(1));
+            stack.push(|| panic!("undo failed"));
+            stack.push(|| log.borrow_mut().push(3));
+        }));
+        assert!(result.is_err());
+        assert_eq!(*log.borrow(), [3, 1]);
+
+        let log = RefCell::new(Vec::new());
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let log = &log;
+            let push = |x| {
+                move || {
+                    assert!(x != 2, "undo failed");
+                    log.borrow_mut().push(x)
+                }
+            };
+            let mut stack = ArrayDropStack::<_, 3>::new();
+            for i in 1..=3 {
+                assert!(stack.push(push(i)).is_ok());
+            }
+        }));
+        assert!(result.is_err());
+        assert_eq!(*log.borrow(), [3, 1]);
+    }
+}
@@ -0,0 +1,200 @@
+//! Owned handles to foreign resources, which are released by move.
+//!
+//! An [`OwnedHandle`] pairs a raw handle `H`, such as a pointer to a C structure, with a release
+//! function that consumes it. The release function is a [`FnOnce`], so it can also take ownership
+//! of any Rust state that must be cleaned up along with the handle.
+//!
+//! ```
+//! use drop_move::ffi::OwnedHandle;
+//! use std::cell::RefCell;
+//!
+//! let closed = RefCell::new(Vec::new());
+//! {
+//!     let label = String::from("config");
+//!     let file = OwnedHandle::new(3, |fd: i32| closed.borrow_mut().push((fd, label)));
+//!     assert_eq!(*file.handle(), 3);
+//! }
+//! assert_eq!(*closed.borrow(), [(3, "config".to_owned())]);
+//! ```
+
+use super::*;
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+use core::fmt;
+
+drop_move_wrap! {
+    /// A handle of type `H` that is passed to `release` when it is dropped.
+    pub struct OwnedHandle<H, R: FnOnce(H)>(OwnedHandleInner {
+        handle: H,
+        release: R,
+    });
+}
+
+impl<H, R: FnOnce(H)> DropMove for OwnedHandleInner<H, R> {
+    fn drop_move(self_: DropHandle<Self>) {
+        let inner = DropHandle::into_inner(self_);
+        (inner.release)(inner.handle);
+    }
+}
+
+impl<H, R: FnOnce(H)> OwnedHandle<H, R> {
+    /// Take ownership of `handle`, which will be released by calling `release`.
+    pub fn new(handle: H, release: R) -> Self {
+        Self::from(OwnedHandleInner { handle, release })
+    }
+
+    /// Borrow the handle.
+    pub fn handle(&self) -> &H {
+        &self.0.handle
+    }
+
+    /// Mutably borrow the handle.
+    pub fn handle_mut(&mut self) -> &mut H {
+        &mut self.0.handle
+    }
+
+    /// Release the handle now.
+    pub fn release(self) {
+        mem::drop(self)
+    }
+
+    /// Split into the handle and its release function, without releasing it.
+    pub fn into_parts(self) -> (H, R) {
+        let inner = OwnedHandleInner::from(self);
+        (inner.handle, inner.release)
+    }
+
+    /// Give up ownership of the handle, e.g. to pass it to foreign code. The release function is
+    /// moved to the heap, and the returned pointer can be stored alongside the handle, such as in a
+    /// `void *` user data field. The handle will not be released unless it is converted back with
+    /// [`from_raw`](Self::from_raw).
+    ///
+    /// Requires the `alloc` feature.
+    #[cfg(feature = "alloc")]
+    pub fn into_raw(self) -> (H, *mut R) {
+        let (handle, release) = self.into_parts();
+        (handle, Box::into_raw(Box::new(release)))
+    }
+
+    /// Take back ownership of a handle that was given up with [`into_raw`](Self::into_raw).
+    ///
+    /// Requires the `alloc` feature.
+    ///
+    /// # Safety
+    ///
+    /// `release` must have been returned by `into_raw`, along with `handle`, and must not be used
+    /// again afterwards.
+    #[cfg(feature = "alloc")]
+    pub unsafe fn from_raw(handle: H, release: *mut R) -> Self {
+        Self::new(handle, *Box::from_raw(release))
+    }
+}
+
+impl<H: fmt::Debug, R: FnOnce(H)> fmt::Debug for OwnedHandle<H, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OwnedHandle").field(&self.0.handle).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    extern crate std;
+    use core::cell::RefCell;
+    use core::ffi::c_void;
+    use core::ptr;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    /// A mock of a C library, which stores a user data pointer for each context. The user data is
+    /// only used with the `alloc` feature.
+    #[allow(dead_code)]
+    mod mock {
+        use super::*;
+
+        pub struct Ctx {
+            pub id: u32,
+            pub user_data: *mut c_void,
+        }
+
+        std::thread_local! {
+            pub static OPEN: RefCell<Vec<u32>> = const { RefCell::new(Vec::new()) };
+        }
+
+        pub extern "C" fn ctx_open(id: u32) -> *mut Ctx {
+            OPEN.with(|open| open.borrow_mut().push(id));
+            Box::into_raw(Box::new(Ctx {
+                id,
+                user_data: ptr::null_mut(),
+            }))
+        }
+
+        pub unsafe extern "C" fn ctx_close(ctx: *mut Ctx) {
+            let ctx = Box::from_raw(ctx);
+            OPEN.with(|open| open.borrow_mut().retain(|id| *id != ctx.id));
+        }
+
+        pub unsafe extern "C" fn ctx_set_user_data(ctx: *mut Ctx, data: *mut c_void) {
+            (*ctx).user_data = data;
+        }
+
+        pub unsafe extern "C" fn ctx_user_data(ctx: *mut Ctx) -> *mut c_void {
+            (*ctx).user_data
+        }
+
+        pub fn open() -> Vec<u32> {
+            OPEN.with(|open| open.borrow().clone())
+        }
+    }
+
+    type Release<'a> = Box<dyn FnOnce(*mut mock::Ctx) + 'a>;
+
+    fn open(id: u32, log: &RefCell<Vec<u32>>) -> OwnedHandle<*mut mock::Ctx, Release<'_>> {
+        let pending = std::vec![id * 10];
+        OwnedHandle::new(
+            mock::ctx_open(id),
+            Box::new(move |ctx| {
+                log.borrow_mut().extend(pending);
+                unsafe { mock::ctx_close(ctx) };
+            }),
+        )
+    }
+
+    #[test]
+    fn release() {
+        let log = RefCell::new(Vec::new());
+        let a = open(1, &log);
+        let b = open(2, &log);
+        assert_eq!(unsafe { (**a.handle()).id }, 1);
+        assert_eq!(mock::open(), [1, 2]);
+
+        b.release();
+        assert_eq!(mock::open(), [1]);
+        mem::drop(a);
+        assert!(mock::open().is_empty());
+        assert_eq!(*log.borrow(), [20, 10]);
+
+        let (ctx, _release) = open(3, &log).into_parts();
+        assert_eq!(mock::open(), [3]);
+        unsafe { mock::ctx_close(ctx) };
+        assert_eq!(*log.borrow(), [20, 10]);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn raw() {
+        let log = RefCell::new(Vec::new());
+        let (ctx, release) = open(4, &log).into_raw();
+        unsafe { mock::ctx_set_user_data(ctx, release as *mut c_void) };
+        assert_eq!(mock::open(), [4]);
+        assert!(log.borrow().is_empty());
+
+        let release = unsafe { mock::ctx_user_data(ctx) } as *mut Release;
+        let handle = unsafe { OwnedHandle::from_raw(ctx, release) };
+        assert_eq!(*handle.handle(), ctx);
+        mem::drop(handle);
+        assert!(mock::open().is_empty());
+        assert_eq!(*log.borrow(), [40]);
+    }
+}
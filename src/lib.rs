@@ -176,11 +176,71 @@ as it implements [`Deref`] for the outer structure, so you can call its methods
 
 See [`drop_move_wrap!`] for the macro's full supported syntax. See the source for [`DropGuard`] for
 the full example.
+
+Enabling the `macros` feature adds the `#[drop_move]` attribute, which is an alternative to
+[`drop_move_wrap!`] that takes an ordinary `struct` or `enum` definition. It supports the full Rust
+syntax for generic parameters and where clauses, and gives better error messages.
+
 */
+#![cfg_attr(feature = "macros", doc = "```")]
+#![cfg_attr(not(feature = "macros"), doc = "```ignore")]
+/*!
+use drop_move::{drop_move, DropMove, DropHandle};
+
+/// Runs a function when dropped.
+#[drop_move(inner = DropGuardInner)]
+pub struct DropGuard<F: FnOnce() + Send> {
+    func: F,
+}
+
+impl<F: FnOnce() + Send> DropMove for DropGuardInner<F> {
+    fn drop_move(self_: DropHandle<Self>) {
+        (DropHandle::into_inner(self_).func)()
+    }
+}
+
+let mut x: u32 = 0;
+{
+    let _guard = DropGuard::from(DropGuardInner { func: || x += 1 });
+}
+
+assert_eq!(x, 1);
+```
+
+# Layout
+
+The outer structure generated by [`drop_move_wrap!`] is `#[repr(transparent)]`, so unless the
+`cancelable` option is used it has the same layout as the inner structure. This is checked at
+compile time for structures without generic parameters. It means that a `repr` attribute, such as
+`#[repr(C)]`, can no longer be given in the shared attributes, as it would conflict. Put it in the
+inner only attributes instead, or use `inner_attr(...)` with `#[drop_move]`.
+
+# Features
+
+- `alloc`: Adds the `pool` module, `BoxDropGuard`, `DropStack`, `DropMoveBox`,
+  `OwnedHandle::into_raw`, and registering a `DropSpawner` for [`AsyncDropMove`].
+- `std`: Implies `alloc`. Adds the `OnSuccess` and `OnUnwind` strategies, which use
+  `std::thread::panicking`, the `PanicPolicy::Report` policy, and lets [`spawn_drop`] park the
+  thread instead of busy waiting. The `must_consume` actions also use `std::thread::panicking` to
+  ignore values dropped while unwinding.
+- `macros`: Adds the `#[drop_move]` attribute.
+- `testing`: Implies `alloc`. Adds the `testing` module, for checking that `DropMove`
+  implementations drop everything exactly once.
+
+None are enabled by default, so the crate is `no_std` compatible out of the box.
+
+The minimum supported Rust version is 1.75.
+*/
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
 
 use core::mem;
 use core::ops::Deref;
 use core::ops::DerefMut;
+use core::pin::Pin;
 use mem::ManuallyDrop;
 
 /// Tracks the relationship between an inner `struct` and outer `struct` generated by
@@ -194,13 +254,31 @@ where
 {
     /// The corresponding outer structure.
     type Outer;
+
+    /// Called by the wrappers just before they call `drop_move`. This is implemented by the
+    /// `tracked` option of [`drop_move_wrap!`], to record the call with a `testing::Tracked`
+    /// value.
+    #[doc(hidden)]
+    fn on_drop_move(_outer: &Self::Outer) {}
 }
 
 /// A variant of [`Drop`] that allows moving out of the value being dropped.
 ///
 /// This trait must be implemented by the inner structure generated by [`drop_move_wrap!`].
 pub trait DropMove: DropMoveTypes {
+    /// What to do if [`drop_move`](Self::drop_move) panics. See [`PanicPolicy`].
+    const PANIC_POLICY: PanicPolicy = PanicPolicy::Unwind;
+
     /// Drop the value, by move instead of by reference.
+    ///
+    /// This is called once per value, except in one case: a hand-written [`From`] implementation
+    /// that panics while converting the outer structure to the inner structure, e.g. in
+    /// [`DropHandle::into_inner`] or when the handle is dropped. The outer structure is then
+    /// dropped while unwinding. With the `std` feature, and an outer structure holding one of this
+    /// crate's wrappers, its members are dropped without calling `drop_move` again. Without `std`
+    /// the unwinding cannot be detected, so `drop_move` is called a second time, with a new handle,
+    /// although each member is still only dropped once. The conversions generated by
+    /// [`drop_move_wrap!`] never panic, so this cannot happen for them.
     fn drop_move(self_: DropHandle<Self>) {
         mem::drop(self_);
     }
@@ -209,21 +287,70 @@ pub trait DropMove: DropMoveTypes {
 mod drop_handle;
 pub use drop_handle::*;
 
+mod partial_handle;
+pub use partial_handle::*;
+
+mod variant_handle;
+pub use variant_handle::*;
+
 mod drop_move_wrap;
 pub use drop_move_wrap::*;
 
 mod drop_guard;
 pub use drop_guard::*;
 
+mod drop_stack;
+pub use drop_stack::*;
+
+mod panic_policy;
+pub use panic_policy::*;
+
+mod try_drop_move;
+pub use try_drop_move::*;
+
+mod async_drop_move;
+pub use async_drop_move::*;
+
+mod pin_drop_move;
+pub use pin_drop_move::*;
+
+#[cfg(feature = "alloc")]
+mod drop_move_dyn;
+#[cfg(feature = "alloc")]
+pub use drop_move_dyn::*;
+
+pub mod must_consume;
+pub use must_consume::ConsumeAction;
+
+pub mod ffi;
+
+#[cfg(feature = "alloc")]
+pub mod pool;
+
+#[cfg(feature = "testing")]
+pub mod testing;
+
+#[cfg(feature = "macros")]
+pub use drop_move_macros::drop_move;
+
+#[cfg(all(test, feature = "macros"))]
+extern crate self as drop_move;
+
+#[cfg(test)]
+mod soundness;
+
 #[cfg(test)]
 mod test {
     use super::*;
 
     extern crate std;
-    use core::cell::RefCell;
+    use core::cell::{Cell, RefCell};
+    use core::fmt::Debug;
     use core::marker::PhantomData;
+    use core::sync::atomic::{AtomicUsize, Ordering};
     use std::boxed::Box;
     use std::rc::Rc;
+    use std::vec;
     use std::vec::Vec;
 
     drop_move_wrap! {
@@ -240,6 +367,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::len_zero)]
     fn return_to_sender() {
         let free_list = Rc::new(RefCell::new(Vec::new()));
 
@@ -285,4 +413,385 @@ mod test {
             false
         }
     }
+
+    drop_move_wrap! {
+        pub struct PlusBounds<'a, 'b: 'a, T: Clone + Into<Vec<u8>> + 'a, U: ?Sized>(
+            PlusBoundsInner(&'a T, &'b U)
+        )
+        where
+            T: Debug + PartialEq<Vec<Vec<u8>>> : Send,
+            for<'c> &'c U: IntoIterator<Item = &'c u8> + Copy;
+    }
+
+    impl<'a, 'b: 'a, T: Clone + Into<Vec<u8>> + 'a, U: ?Sized> DropMove
+        for PlusBoundsInner<'a, 'b, T, U>
+    where
+        T: Debug + PartialEq<Vec<Vec<u8>>> + Send,
+        for<'c> &'c U: IntoIterator<Item = &'c u8> + Copy,
+    {
+    }
+
+    #[test]
+    fn plus_bounds() {
+        #[derive(Clone, Debug, PartialEq)]
+        struct Bytes(Vec<u8>);
+
+        impl From<Bytes> for Vec<u8> {
+            fn from(b: Bytes) -> Self {
+                b.0
+            }
+        }
+
+        impl PartialEq<Vec<Vec<u8>>> for Bytes {
+            fn eq(&self, other: &Vec<Vec<u8>>) -> bool {
+                other.contains(&self.0)
+            }
+        }
+
+        let bytes = Bytes(vec![1, 2]);
+        let slice: &[u8] = &[3, 4];
+        let plus = PlusBounds::from(PlusBoundsInner(&bytes, slice));
+        assert_eq!(plus.0 .0, &bytes);
+        assert_eq!(plus.0 .1.iter().sum::<u8>(), 7);
+    }
+
+    // Long enough to reach the recursion limit if the bounds were parsed one token at a time.
+    drop_move_wrap! {
+        pub struct LongBounds<
+            A: Clone + Send + Sync + 'static,
+            B: Clone + Send + Sync + 'static,
+            C: Clone + Send + Sync + 'static,
+        >(LongBoundsInner(A, B, C))
+        where
+            A: core::fmt::Debug + core::default::Default + core::cmp::PartialEq<A>,
+            B: core::fmt::Debug + core::default::Default + core::cmp::PartialEq<B>,
+            C: core::fmt::Debug + core::default::Default + core::cmp::PartialEq<C>,
+            Vec<A>: core::fmt::Debug + core::clone::Clone,
+            Option<B>: core::fmt::Debug + core::clone::Clone,
+            Result<C, A>: core::fmt::Debug + core::clone::Clone;
+    }
+
+    impl<A, B, C> DropMove for LongBoundsInner<A, B, C>
+    where
+        A: Clone + Send + Sync + 'static + Debug + Default + PartialEq,
+        B: Clone + Send + Sync + 'static + Debug + Default + PartialEq,
+        C: Clone + Send + Sync + 'static + Debug + Default + PartialEq,
+    {
+    }
+
+    #[test]
+    fn long_bounds() {
+        let long = LongBounds::from(LongBoundsInner(1u8, 2u16, 3u32));
+        assert_eq!(LongBoundsInner::from(long).2, 3);
+    }
+
+    drop_move_wrap! {
+        pub struct Ring<T, const N: usize, const M: usize = { 2 * 2 }>(RingInner {
+            items: [Option<T>; N],
+            extra: [u8; M],
+            flushed: Rc<RefCell<Vec<T>>>,
+        });
+    }
+
+    impl<T, const N: usize, const M: usize> DropMove for RingInner<T, N, M> {
+        fn drop_move(self_: DropHandle<Self>) {
+            let inner = DropHandle::into_inner(self_);
+            let mut flushed = inner.flushed.borrow_mut();
+            flushed.extend(IntoIterator::into_iter(inner.items).flatten());
+        }
+    }
+
+    #[test]
+    fn const_generics() {
+        let flushed = Rc::new(RefCell::new(Vec::new()));
+
+        let ring: Ring<u32, 3> = RingInner {
+            items: [Some(1), None, Some(3)],
+            extra: [0; 4],
+            flushed: flushed.clone(),
+        }
+        .into();
+        assert_eq!(ring.0.extra.len(), 4);
+
+        mem::drop(ring);
+        assert_eq!(*flushed.borrow(), [1, 3]);
+    }
+
+    drop_move_wrap! {
+        pub struct Pooled<T, S: Default + Extend<T> = Vec<T>, U = (Box<T>, Vec<Vec<T>>)>(
+            PooledInner(T, Rc<RefCell<S>>, PhantomData<U>)
+        );
+    }
+
+    impl<T, S: Default + Extend<T>, U> DropMove for PooledInner<T, S, U> {
+        fn drop_move(self_: DropHandle<Self>) {
+            let inner = DropHandle::into_inner(self_);
+            inner.1.borrow_mut().extend(Some(inner.0));
+        }
+    }
+
+    #[test]
+    fn default_params() {
+        let pool = Rc::new(RefCell::new(Vec::new()));
+
+        let pooled: Pooled<u32> = PooledInner(5, pool.clone(), PhantomData).into();
+        mem::drop(pooled);
+        assert_eq!(*pool.borrow(), [5]);
+    }
+
+    drop_move_wrap! {
+        #[derive(Default, PartialEq)]
+        #[drop_move(cancelable)]
+        pub struct Counter(CounterInner(Rc<Cell<u32>>));
+    }
+
+    impl DropMove for CounterInner {
+        fn drop_move(self_: DropHandle<Self>) {
+            self_.0 .0.set(self_.0 .0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn cancel() {
+        let count = Rc::new(Cell::new(0));
+
+        mem::drop(Counter::from(CounterInner(count.clone())));
+        assert_eq!(count.get(), 1);
+
+        let mut counter = Counter::from(CounterInner(count.clone()));
+        assert!(!Counter::is_canceled(&counter));
+        Counter::cancel(&mut counter);
+        assert!(Counter::is_canceled(&counter));
+        mem::drop(counter);
+        assert_eq!(count.get(), 1);
+
+        let counter = Counter::from(CounterInner(count.clone()));
+        let inner = Counter::defuse(counter);
+        mem::drop(inner);
+        assert_eq!(count.get(), 1);
+        assert_eq!(Rc::strong_count(&count), 1);
+
+        let mut canceled = Counter::from(CounterInner(count.clone()));
+        Counter::cancel(&mut canceled);
+        assert!(canceled == Counter::from(CounterInner(count.clone())));
+        mem::drop(canceled);
+        assert_eq!(count.get(), 2);
+
+        mem::drop(Counter::default());
+    }
+
+    drop_move_wrap! {
+        pub struct Limit(LimitInner {
+            max: usize,
+            used: &'static AtomicUsize,
+        });
+    }
+
+    impl DropMove for LimitInner {
+        fn drop_move(self_: DropHandle<Self>) {
+            self_.0.used.fetch_add(self_.0.max, Ordering::Relaxed);
+        }
+    }
+
+    const fn limit(max: usize, used: &'static AtomicUsize) -> Limit {
+        Limit::new_from_inner(LimitInner { max, used })
+    }
+
+    static USED: AtomicUsize = AtomicUsize::new(0);
+    static LIMIT: Limit = limit(8, &USED);
+
+    #[test]
+    fn const_new() {
+        assert_eq!(LIMIT.0.max, 8);
+        mem::drop(limit(1, &USED));
+        mem::drop(limit(1, &USED));
+        assert_eq!(USED.load(Ordering::Relaxed), 2);
+    }
+
+    drop_move_wrap! {
+        pub struct Header(
+            #[repr(C)]
+            HeaderInner {
+                tag: u8,
+                len: u32,
+            }
+        );
+    }
+
+    impl DropMove for HeaderInner {}
+
+    #[test]
+    fn layout() {
+        assert_eq!(mem::size_of::<Header>(), mem::size_of::<HeaderInner>());
+        assert_eq!(mem::align_of::<Header>(), mem::align_of::<HeaderInner>());
+        assert!(mem::size_of::<Counter>() > mem::size_of::<CounterInner>());
+
+        let header = Header::from(HeaderInner { tag: 1, len: 16 });
+        let inner = unsafe { &*(&header as *const Header as *const HeaderInner) };
+        assert_eq!((inner.tag, inner.len), (1, 16));
+    }
+
+    static RELEASED: AtomicUsize = AtomicUsize::new(0);
+
+    drop_move_wrap! {
+        #[derive(Default)]
+        pub struct Token(TokenInner);
+    }
+
+    impl DropMove for TokenInner {
+        fn drop_move(_self: DropHandle<Self>) {
+            RELEASED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    static TOKEN: Token = Token::new();
+
+    #[test]
+    fn unit() {
+        assert_eq!(mem::size_of::<Token>(), 0);
+        mem::drop(Token::new());
+        mem::drop(Token::default());
+        mem::drop(Token::from(TokenInner));
+        assert_eq!(RELEASED.load(Ordering::Relaxed), 3);
+
+        let _ = &TOKEN;
+        let _inner: TokenInner = Token::new().into();
+        assert_eq!(RELEASED.load(Ordering::Relaxed), 3);
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod test_macros {
+    use super::*;
+
+    extern crate std;
+    use core::cell::{Cell, RefCell};
+    use core::fmt::Debug;
+    use core::marker::PhantomData;
+    use std::boxed::Box;
+    use std::rc::Rc;
+    use std::vec::Vec;
+
+    #[drop_move(inner = pub ReturnToSenderInner)]
+    pub struct ReturnToSender<T: Clone + Debug, const N: usize = 4>(
+        [T; N],
+        Rc<RefCell<Vec<[T; N]>>>,
+    );
+
+    impl<T: Clone + Debug, const N: usize> DropMove for ReturnToSenderInner<T, N> {
+        fn drop_move(self_: DropHandle<Self>) {
+            let inner = DropHandle::into_inner(self_);
+            inner.1.borrow_mut().push(inner.0);
+        }
+    }
+
+    #[test]
+    fn return_to_sender() {
+        let free_list = Rc::new(RefCell::new(Vec::new()));
+
+        let rts: ReturnToSender<u32> = ReturnToSenderInner([1, 2, 3, 4], free_list.clone()).into();
+        assert!(free_list.borrow().is_empty());
+
+        mem::drop(rts);
+
+        assert_eq!(*free_list.borrow(), [[1, 2, 3, 4]]);
+    }
+
+    #[drop_move(inner = CounterInner, cancelable)]
+    pub struct Counter(Rc<Cell<u32>>);
+
+    impl DropMove for CounterInner {
+        fn drop_move(self_: DropHandle<Self>) {
+            self_.0 .0.set(self_.0 .0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn cancel() {
+        let count = Rc::new(Cell::new(0));
+
+        mem::drop(Counter::from(CounterInner(count.clone())));
+        let mut counter = Counter::from(CounterInner(count.clone()));
+        Counter::cancel(&mut counter);
+        mem::drop(counter);
+        assert_eq!(count.get(), 1);
+    }
+
+    #[drop_move(inner = pub(crate) WrapStressTest1, outer_attr(derive(PartialEq)))]
+    #[derive(Clone)]
+    #[drop_move(inner_attr(derive(PartialOrd)), inner_attr(allow(dead_code)))]
+    pub enum WrapStressTest<'a, T: Deref + 'a>
+    where
+        T::Target: Clone + 'a,
+    {
+        Foo(PhantomData<&'a ()>),
+        Bar(T),
+    }
+
+    impl<'a, T: Deref + 'a> DropMove for WrapStressTest1<'a, T> where T::Target: Clone + 'a {}
+
+    impl<'a, T: Deref + 'a> PartialEq for WrapStressTest1<'a, T>
+    where
+        T::Target: Clone + 'a,
+    {
+        fn eq(&self, _other: &Self) -> bool {
+            false
+        }
+    }
+
+    std::thread_local! {
+        static RELEASED: Cell<u32> = const { Cell::new(0) };
+    }
+
+    #[drop_move(inner = TokenInner)]
+    #[derive(Default)]
+    pub struct Token;
+
+    impl DropMove for TokenInner {
+        fn drop_move(_self: DropHandle<Self>) {
+            RELEASED.with(|released| released.set(released.get() + 1));
+        }
+    }
+
+    #[test]
+    fn unit() {
+        const TOKEN: Token = Token::new();
+        mem::drop(TOKEN);
+        mem::drop(Token::default());
+        mem::drop(Token::from(TokenInner));
+        RELEASED.with(|released| assert_eq!(released.get(), 3));
+    }
+
+    #[drop_move(inner = PinnedInner, pin)]
+    pub struct Pinned {
+        count: Rc<Cell<u32>>,
+    }
+
+    impl PinDropMove for PinnedInner {
+        fn drop_move_pinned(self_: PinDropHandle<Self>) {
+            self_.count.set(self_.count.get() + 1);
+        }
+    }
+
+    #[test]
+    fn pin() {
+        let count = Rc::new(Cell::new(0));
+        let mut pinned = Box::pin(Pinned::from(PinnedInner {
+            count: count.clone(),
+        }));
+        assert_eq!(pinned.as_mut().as_pin_mut().count().get(), 0);
+        mem::drop(pinned);
+        assert_eq!(count.get(), 1);
+    }
+
+    // As when the crate has been renamed, or is re-exported from another crate.
+    #[drop_move(inner = RenamedInner, crate = crate)]
+    pub struct Renamed(u8);
+
+    impl DropMove for RenamedInner {}
+
+    #[test]
+    fn renamed() {
+        assert_eq!(Renamed::from(RenamedInner(1)).0 .0, 1);
+    }
 }
@@ -0,0 +1,209 @@
+//! Actions for types that must be explicitly consumed, rather than dropped.
+
+/// The action to take when a value that must be consumed is dropped instead.
+///
+/// The implementations in this module are selected with the `must_consume` option of
+/// [`drop_move_wrap!`](crate::drop_move_wrap), e.g. `#[drop_move(must_consume(Abort))]`. This
+/// generates a [`DropMove`](crate::DropMove) implementation that calls
+/// [`dropped`](Self::dropped), and a method `fn consume(self) -> Inner` on the outer structure for
+/// converting to the inner structure without triggering the action. It is intended for linear
+/// types, like a transaction that must either be committed or rolled back.
+///
+/// With the `std` feature, none of these actions are taken while the thread is already panicking,
+/// as a value dropped while unwinding was abandoned by that panic rather than forgotten, and
+/// panicking again would abort the process. Without `std` there is no way to tell, so dropping the
+/// value while unwinding takes the action, and [`Panic`] will abort.
+///
+/// ```should_panic
+/// use drop_move::drop_move_wrap;
+///
+/// drop_move_wrap! {
+///     #[drop_move(must_consume(Panic))]
+///     pub struct Transaction(TransactionInner {
+///         id: u32,
+///     });
+/// }
+///
+/// impl Transaction {
+///     pub fn commit(self) {
+///         let inner = self.consume();
+///         // ...
+///     }
+/// }
+///
+/// Transaction::from(TransactionInner { id: 0 }).commit();
+///
+/// // Panics, because it was not committed.
+/// Transaction::from(TransactionInner { id: 1 });
+/// ```
+pub trait ConsumeAction {
+    /// Called when a value of type `T` was dropped without being consumed. If this returns, the
+    /// members of the value will be dropped normally.
+    fn dropped<T: ?Sized>();
+}
+
+/// Panic when dropped. This is the default [`ConsumeAction`].
+#[derive(Clone, Copy, Debug)]
+pub enum Panic {}
+
+impl ConsumeAction for Panic {
+    fn dropped<T: ?Sized>() {
+        if unwinding() {
+            return;
+        }
+
+        panic!(
+            "`{}` was dropped without being consumed",
+            core::any::type_name::<T>()
+        );
+    }
+}
+
+/// Abort the process when dropped.
+///
+/// Without the `std` feature, this is done by panicking while panicking.
+#[derive(Clone, Copy, Debug)]
+pub enum Abort {}
+
+impl ConsumeAction for Abort {
+    fn dropped<T: ?Sized>() {
+        #[cfg(feature = "std")]
+        if !unwinding() {
+            std::process::abort();
+        }
+
+        #[cfg(not(feature = "std"))]
+        {
+            struct Bomb;
+            impl Drop for Bomb {
+                fn drop(&mut self) {
+                    panic!("aborting");
+                }
+            }
+
+            let _bomb = Bomb;
+            Panic::dropped::<T>();
+        }
+    }
+}
+
+/// Panic when dropped, but only if debug assertions are enabled. Otherwise, the members will just
+/// be dropped normally.
+#[derive(Clone, Copy, Debug)]
+pub enum DebugAssert {}
+
+impl ConsumeAction for DebugAssert {
+    fn dropped<T: ?Sized>() {
+        #[cfg(debug_assertions)]
+        Panic::dropped::<T>();
+    }
+}
+
+/// Fail to link if the optimizer cannot prove that the value is never dropped. This only applies
+/// when debug assertions are disabled, which usually means in release builds. Otherwise, it falls
+/// back to [`Panic`].
+///
+/// The linker error will mention that a value that must be consumed was dropped, but will not say
+/// where. It is also possible that the optimizer fails to remove a drop that can never happen, so
+/// this is best used alongside tests in debug builds.
+#[derive(Clone, Copy, Debug)]
+pub enum LinkError {}
+
+impl ConsumeAction for LinkError {
+    fn dropped<T: ?Sized>() {
+        #[cfg(debug_assertions)]
+        Panic::dropped::<T>();
+
+        #[cfg(not(debug_assertions))]
+        {
+            extern "C" {
+                #[link_name = "\n\nERROR[drop_move]: a value that must be consumed may be \
+                               dropped\n\n"]
+                fn must_consume_dropped() -> !;
+            }
+
+            unsafe { must_consume_dropped() }
+        }
+    }
+}
+
+/// Check whether the thread is unwinding from a panic, which can only be detected with `std`.
+fn unwinding() -> bool {
+    #[cfg(feature = "std")]
+    {
+        std::thread::panicking()
+    }
+
+    #[cfg(not(feature = "std"))]
+    {
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::*;
+
+    extern crate std;
+    use core::cell::Cell;
+    use std::rc::Rc;
+
+    drop_move_wrap! {
+        #[drop_move(must_consume)]
+        pub struct Linear(LinearInner(Rc<Cell<u32>>));
+    }
+
+    drop_move_wrap! {
+        #[drop_move(must_consume(DebugAssert))]
+        pub struct DebugLinear<T>(DebugLinearInner(T));
+    }
+
+    impl Linear {
+        fn finish(self) {
+            let inner = self.consume();
+            inner.0.set(inner.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn consume() {
+        let count = Rc::new(Cell::new(0));
+        Linear::from(LinearInner(count.clone())).finish();
+        assert_eq!(count.get(), 1);
+        assert_eq!(Rc::strong_count(&count), 1);
+
+        let inner = DebugLinear::from(DebugLinearInner(count.clone())).consume();
+        mem::drop(inner);
+        assert_eq!(Rc::strong_count(&count), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "was dropped without being consumed")]
+    fn dropped() {
+        let _ = Linear::from(LinearInner(Rc::new(Cell::new(0))));
+    }
+
+    #[test]
+    #[cfg_attr(debug_assertions, should_panic(expected = "DebugLinear"))]
+    fn debug_dropped() {
+        let _ = DebugLinear::from(DebugLinearInner(()));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn unwinding() {
+        use std::panic::{self, AssertUnwindSafe};
+
+        let count = Rc::new(Cell::new(0));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _linear = Linear::from(LinearInner(count.clone()));
+            let _debug = DebugLinear::from(DebugLinearInner(count.clone()));
+            panic!("unrelated");
+        }));
+        assert_eq!(
+            *result.unwrap_err().downcast::<&str>().unwrap(),
+            "unrelated"
+        );
+        assert_eq!(Rc::strong_count(&count), 1);
+    }
+}
@@ -0,0 +1,271 @@
+use super::*;
+
+#[cfg(feature = "std")]
+use std::any::Any;
+#[cfg(feature = "std")]
+use std::boxed::Box;
+#[cfg(feature = "std")]
+use std::sync::RwLock;
+
+/// What to do when [`drop_move`](DropMove::drop_move) panics, selected with
+/// [`DropMove::PANIC_POLICY`] or [`PinDropMove::PANIC_POLICY`].
+///
+/// Whatever the policy, every member that has not been moved out of the [`DropHandle`] is dropped
+/// exactly once, as the handle is dropped while unwinding. Members that were moved out are dropped
+/// by whoever owns them at the time. If dropping a member panics while already unwinding from
+/// `drop_move`, the process aborts, as with any other panic during unwinding.
+///
+/// ```
+/// use drop_move::{drop_move_wrap, DropHandle, DropMove, PanicPolicy};
+///
+/// drop_move_wrap! {
+///     pub struct Flusher(FlusherInner {
+///         buffer: Vec<u8>,
+///     });
+/// }
+///
+/// impl DropMove for FlusherInner {
+///     // Better to abort than to leave the output half written.
+///     const PANIC_POLICY: PanicPolicy = PanicPolicy::Abort;
+///
+///     fn drop_move(self_: DropHandle<Self>) {
+///         let buffer = DropHandle::into_inner(self_).buffer;
+///         // Write out the buffer.
+///     }
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PanicPolicy {
+    /// Let the panic continue to unwind. This is the default.
+    Unwind,
+
+    /// Abort the process.
+    ///
+    /// Without the `std` feature, this is done by panicking while panicking.
+    Abort,
+
+    /// Catch the panic, so that the code that dropped the value continues normally. The panic is
+    /// still printed by the panic hook, and its payload is then passed to the function registered
+    /// with [`set_drop_panic_reporter`], if there is one.
+    ///
+    /// Requires the `std` feature.
+    #[cfg(feature = "std")]
+    Report,
+}
+
+impl PanicPolicy {
+    /// Run `f`, which calls `drop_move`, handling any panic according to the policy.
+    pub(crate) fn run(self, f: impl FnOnce()) {
+        match self {
+            PanicPolicy::Unwind => f(),
+            PanicPolicy::Abort => {
+                struct Bomb;
+                impl Drop for Bomb {
+                    fn drop(&mut self) {
+                        #[cfg(feature = "std")]
+                        std::process::abort();
+
+                        #[cfg(not(feature = "std"))]
+                        panic!("aborting, as drop_move panicked");
+                    }
+                }
+
+                let bomb = Bomb;
+                f();
+                mem::forget(bomb);
+            }
+            #[cfg(feature = "std")]
+            PanicPolicy::Report => {
+                // The value is being dropped, so nothing can observe it in a broken state.
+                let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f));
+                if let Err(payload) = result {
+                    let reporter = *REPORTER.read().unwrap_or_else(|e| e.into_inner());
+                    if let Some(reporter) = reporter {
+                        reporter(payload);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+type Reporter = fn(Box<dyn Any + Send>);
+
+#[cfg(feature = "std")]
+static REPORTER: RwLock<Option<Reporter>> = RwLock::new(None);
+
+/// Register the function that is passed the payload of each panic caught by
+/// [`PanicPolicy::Report`], replacing any previous one. Passing [`None`] goes back to ignoring
+/// them.
+///
+/// Requires the `std` feature.
+#[cfg(feature = "std")]
+pub fn set_drop_panic_reporter(reporter: Option<fn(Box<dyn Any + Send>)>) {
+    *REPORTER.write().unwrap_or_else(|e| e.into_inner()) = reporter;
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+
+    use core::cell::Cell;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use std::panic::{self, AssertUnwindSafe};
+    use std::rc::Rc;
+
+    struct Counted<'a>(&'a Cell<u32>);
+
+    impl<'a> Drop for Counted<'a> {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    drop_move_wrap! {
+        #[drop_move(fields)]
+        pub struct Unwinding<'a>(UnwindingInner {
+            moved: Counted<'a>,
+            kept: Counted<'a>,
+            shared: Rc<()>,
+        });
+    }
+
+    impl<'a> DropMove for UnwindingInner<'a> {
+        fn drop_move(self_: DropHandle<Self>) {
+            let mut handle = PartialHandle::new(self_);
+            let _moved = PartialHandle::fields(&mut handle).moved.take();
+            panic!("drop_move failed");
+        }
+    }
+
+    drop_move_wrap! {
+        #[drop_move(cancelable)]
+        pub struct Reported<'a>(ReportedInner {
+            first: Counted<'a>,
+            second: Counted<'a>,
+        });
+    }
+
+    impl<'a> DropMove for ReportedInner<'a> {
+        const PANIC_POLICY: PanicPolicy = PanicPolicy::Report;
+
+        fn drop_move(self_: DropHandle<Self>) {
+            assert_eq!(self_.0.first.0.get() + self_.0.second.0.get(), 1);
+            panic!("drop_move failed");
+        }
+    }
+
+    drop_move_wrap! {
+        #[drop_move(pin)]
+        pub struct Pinned<'a>(PinnedInner {
+            kept: Counted<'a>,
+        });
+    }
+
+    impl<'a> PinDropMove for PinnedInner<'a> {
+        const PANIC_POLICY: PanicPolicy = PanicPolicy::Report;
+
+        fn drop_move_pinned(_self: PinDropHandle<Self>) {
+            panic!("drop_move_pinned failed");
+        }
+    }
+
+    drop_move_wrap! {
+        pub struct Aborting(AbortingInner);
+    }
+
+    impl DropMove for AbortingInner {
+        const PANIC_POLICY: PanicPolicy = PanicPolicy::Abort;
+
+        fn drop_move(_self: DropHandle<Self>) {
+            panic!("drop_move failed");
+        }
+    }
+
+    #[test]
+    fn unwind() {
+        let moved = Cell::new(0);
+        let kept = Cell::new(0);
+        let shared = Rc::new(());
+        let value = Unwinding::from(UnwindingInner {
+            moved: Counted(&moved),
+            kept: Counted(&kept),
+            shared: shared.clone(),
+        });
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| mem::drop(value)));
+        assert!(result.is_err());
+        assert_eq!((moved.get(), kept.get()), (1, 1));
+        assert_eq!(Rc::strong_count(&shared), 1);
+    }
+
+    static REPORTED: AtomicUsize = AtomicUsize::new(0);
+
+    fn report(payload: Box<dyn Any + Send>) {
+        assert!(payload.downcast_ref::<&str>().unwrap().ends_with("failed"));
+        REPORTED.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn report_and_abort() {
+        set_drop_panic_reporter(Some(report));
+
+        let first = Cell::new(0);
+        let kept = Cell::new(1);
+        mem::drop(Reported::from(ReportedInner {
+            first: Counted(&first),
+            second: Counted(&kept),
+        }));
+        assert_eq!((first.get(), kept.get()), (1, 2));
+
+        mem::drop(Pinned::from(PinnedInner {
+            kept: Counted(&kept),
+        }));
+        assert_eq!(kept.get(), 3);
+        assert_eq!(REPORTED.load(Ordering::Relaxed), 2);
+
+        // Boxing the inner structure keeps its policy.
+        let boxed = Cell::new(0);
+        let other = Cell::new(1);
+        mem::drop(DropMoveBox::new(ReportedInner {
+            first: Counted(&boxed),
+            second: Counted(&other),
+        }));
+        assert_eq!((boxed.get(), other.get()), (1, 2));
+        assert_eq!(REPORTED.load(Ordering::Relaxed), 3);
+
+        set_drop_panic_reporter(None);
+
+        // Nothing happens if there is no panic.
+        PanicPolicy::Abort.run(|| kept.set(4));
+        assert_eq!(kept.get(), 4);
+    }
+
+    // Only does anything when run in a child process by `abort`, as it does not return.
+    #[test]
+    fn abort_child() {
+        if std::env::var_os("DROP_MOVE_ABORT_CHILD").is_some() {
+            mem::drop(Aborting::from(AbortingInner));
+        }
+    }
+
+    #[test]
+    fn abort() {
+        let status = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "panic_policy::test::abort_child"])
+            .env("DROP_MOVE_ABORT_CHILD", "1")
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .unwrap();
+        assert!(!status.success());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            assert_eq!(status.signal(), Some(6), "expected SIGABRT, got {}", status);
+        }
+    }
+}
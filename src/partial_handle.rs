@@ -0,0 +1,180 @@
+use super::*;
+
+/// Splits an inner structure into its fields, each wrapped in an [`Option`] so that they can be
+/// moved out one at a time.
+///
+/// This is implemented by [`drop_move_wrap!`] when the `fields` option is given, which requires
+/// that the inner structure has named fields. The generated [`Fields`](Self::Fields) structure has
+/// a field of type `Option<Field>` for each field of the inner structure, with the same name and
+/// visibility. It is used through a [`PartialHandle`].
+pub trait DropMoveFields: DropMoveTypes {
+    /// The fields, each wrapped in an [`Option`].
+    type Fields;
+
+    /// Split the structure into its fields, all of which will be [`Some`].
+    fn into_fields(self) -> Self::Fields;
+}
+
+/// A [`DropHandle`] that allows individual fields to be moved out.
+///
+/// The outer structure is available through [`as_outer`](Self::as_outer) until the first call to
+/// [`fields`](Self::fields), which splits the structure into its [`Fields`](DropMoveFields::Fields)
+/// so that they can be taken with [`Option::take`]. Any fields that are left will be dropped
+/// normally.
+///
+/// ```
+/// use drop_move::{drop_move_wrap, DropHandle, DropMove, PartialHandle};
+///
+/// drop_move_wrap! {
+///     #[drop_move(fields)]
+///     pub struct Job<F: FnOnce(&str)>(JobInner {
+///         name: String,
+///         func: F,
+///     });
+/// }
+///
+/// impl<F: FnOnce(&str)> Job<F> {
+///     fn label(&self) -> String {
+///         format!("job {}", self.0.name)
+///     }
+/// }
+///
+/// impl<F: FnOnce(&str)> DropMove for JobInner<F> {
+///     fn drop_move(self_: DropHandle<Self>) {
+///         let mut handle = PartialHandle::new(self_);
+///         let label = PartialHandle::as_outer(&handle).unwrap().label();
+///
+///         let func = PartialHandle::fields(&mut handle).func.take().unwrap();
+///         assert!(PartialHandle::as_outer(&handle).is_none());
+///         func(&label);
+///
+///         // The name is dropped with the handle.
+///     }
+/// }
+///
+/// let mut finished = String::new();
+/// {
+///     let _job = Job::from(JobInner {
+///         name: "cleanup".to_owned(),
+///         func: |label: &str| finished.push_str(label),
+///     });
+/// }
+/// assert_eq!(finished, "job cleanup");
+/// ```
+pub struct PartialHandle<T: DropMoveFields>(PartialState<T>);
+
+enum PartialState<T: DropMoveFields> {
+    Whole(DropHandle<T>),
+    Split(T::Fields),
+    Empty,
+}
+
+impl<T: DropMoveFields> PartialHandle<T> {
+    /// Start moving fields out of a [`DropHandle`].
+    pub fn new(handle: DropHandle<T>) -> Self {
+        PartialHandle(PartialState::Whole(handle))
+    }
+
+    /// Borrow the outer structure, unless it has already been split into its fields.
+    ///
+    /// This is an associated function to be consistent with [`DropHandle`].
+    pub fn as_outer(self_: &Self) -> Option<&T::Outer> {
+        match &self_.0 {
+            PartialState::Whole(handle) => Some(handle.deref()),
+            _ => None,
+        }
+    }
+
+    /// Mutably borrow the outer structure, unless it has already been split into its fields.
+    pub fn as_outer_mut(self_: &mut Self) -> Option<&mut T::Outer> {
+        match &mut self_.0 {
+            PartialState::Whole(handle) => Some(handle.deref_mut()),
+            _ => None,
+        }
+    }
+
+    /// Split the structure into its fields, if this hasn't been done already, and borrow them.
+    pub fn fields(self_: &mut Self) -> &mut T::Fields {
+        let fields = Self::split(mem::replace(&mut self_.0, PartialState::Empty));
+        self_.0 = PartialState::Split(fields);
+        match &mut self_.0 {
+            PartialState::Split(fields) => fields,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Convert to the fields that have not yet been taken.
+    pub fn into_fields(self_: Self) -> T::Fields {
+        Self::split(self_.0)
+    }
+
+    fn split(state: PartialState<T>) -> T::Fields {
+        match state {
+            PartialState::Whole(handle) => DropHandle::into_inner(handle).into_fields(),
+            PartialState::Split(fields) => fields,
+            PartialState::Empty => unreachable!(),
+        }
+    }
+}
+
+impl<T: DropMoveFields> From<DropHandle<T>> for PartialHandle<T> {
+    fn from(handle: DropHandle<T>) -> Self {
+        Self::new(handle)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    extern crate std;
+    use core::cell::RefCell;
+    use std::rc::Rc;
+    use std::vec::Vec;
+
+    drop_move_wrap! {
+        #[drop_move(fields)]
+        pub struct Session(SessionInner {
+            id: u32,
+            log: Rc<RefCell<Vec<u32>>>,
+            pub(crate) token: Rc<()>,
+        });
+    }
+
+    impl Session {
+        fn id(&self) -> u32 {
+            self.0.id
+        }
+    }
+
+    impl DropMove for SessionInner {
+        fn drop_move(self_: DropHandle<Self>) {
+            let mut handle = PartialHandle::new(self_);
+            let id = PartialHandle::as_outer(&handle).unwrap().id();
+            PartialHandle::as_outer_mut(&mut handle).unwrap().0.id += 1;
+
+            let log = PartialHandle::fields(&mut handle).log.take().unwrap();
+            assert!(PartialHandle::as_outer(&handle).is_none());
+            log.borrow_mut().push(id);
+
+            let fields = PartialHandle::into_fields(handle);
+            assert!(fields.log.is_none());
+            log.borrow_mut().push(fields.id.unwrap());
+        }
+    }
+
+    #[test]
+    fn partial() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let token = Rc::new(());
+        mem::drop(Session::from(SessionInner {
+            id: 5,
+            log: log.clone(),
+            token: token.clone(),
+        }));
+
+        assert_eq!(*log.borrow(), [5, 6]);
+        assert_eq!(Rc::strong_count(&log), 1);
+        assert_eq!(Rc::strong_count(&token), 1);
+    }
+}
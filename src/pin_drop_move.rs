@@ -0,0 +1,248 @@
+use super::*;
+use core::cell::Cell;
+use core::marker::PhantomData;
+use core::ptr;
+
+/// A variant of [`DropMove`] for types that may be pinned, such as futures or intrusive list
+/// nodes.
+///
+/// Use the `pin` option of [`drop_move_wrap!`] to have the outer structure hold a
+/// [`PinDropMoveWrapper`], which calls [`drop_move_pinned`](Self::drop_move_pinned) instead of
+/// [`drop_move`](DropMove::drop_move). Since a pinned value cannot be moved, it is dropped in place
+/// through a [`PinDropHandle`]. The option also generates a method
+/// `fn as_pin_mut(self: Pin<&mut Self>) -> Pin<&mut Inner>` on the outer structure, and, if the
+/// inner structure has named fields, a method `fn field(self: Pin<&mut Self>) -> Pin<&mut Field>`
+/// on the inner structure for each field. Every field is structurally pinned, so the inner
+/// structure may not implement [`Drop`], and is only [`Unpin`] if all of its fields are. A field
+/// that is [`Unpin`] can still be moved out of with [`Pin::get_mut`].
+///
+/// ```
+/// use core::marker::PhantomPinned;
+/// use core::pin::Pin;
+/// use drop_move::{drop_move_wrap, PinDropHandle, PinDropMove};
+///
+/// drop_move_wrap! {
+///     #[drop_move(pin)]
+///     pub struct Node(NodeInner {
+///         name: Option<String>,
+///         _pin: PhantomPinned,
+///     });
+/// }
+///
+/// impl PinDropMove for NodeInner {
+///     fn drop_move_pinned(mut self_: PinDropHandle<Self>) {
+///         let name: Option<String> = PinDropHandle::as_mut(&mut self_).name().get_mut().take();
+///         // Unlink the node from its list, then use the name.
+///     }
+/// }
+///
+/// let node = Box::pin(Node::from(NodeInner {
+///     name: Some("node".to_owned()),
+///     _pin: PhantomPinned,
+/// }));
+/// ```
+///
+/// The `Unpin` implementations are generated by the macro, so writing another one is an error.
+///
+/// ```compile_fail
+/// # use core::marker::PhantomPinned;
+/// # use drop_move::{drop_move_wrap, PinDropMove};
+/// drop_move_wrap! {
+///     #[drop_move(pin)]
+///     pub struct Node(NodeInner {
+///         _pin: PhantomPinned,
+///     });
+/// }
+///
+/// impl PinDropMove for NodeInner {}
+///
+/// // Conflicts with the generated implementation.
+/// impl Unpin for NodeInner {}
+/// ```
+///
+/// Options that implement [`DropMove`], such as `try_drop`, cannot be combined with `pin`.
+pub trait PinDropMove: DropMoveTypes {
+    /// What to do if [`drop_move_pinned`](Self::drop_move_pinned) panics. See [`PanicPolicy`].
+    const PANIC_POLICY: PanicPolicy = PanicPolicy::Unwind;
+
+    /// Drop the value in place. Anything that is left in the structure is dropped once this
+    /// returns.
+    fn drop_move_pinned(_: PinDropHandle<Self>) {}
+}
+
+/// A pinned reference to a structure that is being dropped, which is passed to
+/// [`drop_move_pinned`](PinDropMove::drop_move_pinned). Once that returns, the structure is
+/// dropped in place, unless it was moved out with [`into_inner`](Self::into_inner). This happens
+/// even if the handle was forgotten or `drop_move_pinned` panicked, as the memory of a pinned value
+/// cannot be reused until it has been dropped.
+///
+/// Unlike a [`DropHandle`], this dereferences to the inner structure `T`, as the outer structure
+/// cannot be reconstructed without moving it. Use [`as_mut`](Self::as_mut) to get a pinned mutable
+/// reference.
+#[derive(Debug)]
+pub struct PinDropHandle<'a, T>(Pin<&'a mut T>, &'a Cell<bool>);
+
+impl<'a, T: PinDropMove> PinDropHandle<'a, T> {
+    /// Call [`drop_move_pinned`](PinDropMove::drop_move_pinned), then drop `x` in place if it was
+    /// not moved out of the handle. The drop is done by a guard, so that it happens even if
+    /// `drop_move_pinned` unwinds.
+    ///
+    /// Safety: `x` must be pinned, and must not be used again, as it will have been dropped or
+    /// moved.
+    pub(crate) unsafe fn drop_in_place(x: &mut T) {
+        struct Guard<'b, T>(*mut T, &'b Cell<bool>);
+
+        impl<'b, T> Drop for Guard<'b, T> {
+            fn drop(&mut self) {
+                if !self.1.get() {
+                    unsafe { ptr::drop_in_place(self.0) };
+                }
+            }
+        }
+
+        let moved = Cell::new(false);
+        let guard = Guard(x, &moved);
+        let handle = PinDropHandle(Pin::new_unchecked(&mut *guard.0), &moved);
+        T::PANIC_POLICY.run(|| PinDropMove::drop_move_pinned(handle));
+    }
+}
+
+impl<'a, T> PinDropHandle<'a, T> {
+    /// Get a pinned mutable reference to the structure, e.g. for projecting to its fields.
+    ///
+    /// This is an associated function so that will not conflict with any methods of `T`, which
+    /// are accessible through [`Deref`].
+    pub fn as_mut(self_: &mut Self) -> Pin<&mut T> {
+        self_.0.as_mut()
+    }
+
+    /// Move the structure out, which is only possible if it is [`Unpin`].
+    ///
+    /// This is an associated function so that will not conflict with any methods of `T`, which
+    /// are accessible through [`Deref`].
+    pub fn into_inner(self_: Self) -> T
+    where
+        T: Unpin,
+    {
+        self_.1.set(true);
+        unsafe { ptr::read(&*self_.0) }
+    }
+}
+
+impl<'a, T> Deref for PinDropHandle<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.0.deref()
+    }
+}
+
+/// `Unpin` exactly when `T` is, for use in the bounds of the `Unpin` implementations generated by
+/// [`drop_move_wrap!`].
+#[doc(hidden)]
+pub struct UnpinIf<'a, T: ?Sized>(PhantomData<&'a ()>, PhantomData<T>);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    extern crate std;
+    use core::cell::RefCell;
+    use core::marker::PhantomPinned;
+    use std::boxed::Box;
+    use std::rc::Rc;
+    use std::string::String;
+    use std::vec::Vec;
+
+    drop_move_wrap! {
+        #[drop_move(pin)]
+        pub struct Node(NodeInner {
+            name: String,
+            log: Rc<RefCell<Vec<(String, usize)>>>,
+            _pin: PhantomPinned,
+        });
+    }
+
+    impl PinDropMove for NodeInner {
+        fn drop_move_pinned(mut self_: PinDropHandle<Self>) {
+            let address = &*self_ as *const Self as usize;
+            let this = PinDropHandle::as_mut(&mut self_);
+            let name = mem::take(this.name().get_mut());
+            self_.log.borrow_mut().push((name, address));
+        }
+    }
+
+    drop_move_wrap! {
+        #[drop_move(pin)]
+        pub struct Movable(MovableInner(Rc<RefCell<Vec<(String, usize)>>>));
+    }
+
+    impl PinDropMove for MovableInner {
+        fn drop_move_pinned(self_: PinDropHandle<Self>) {
+            let inner = PinDropHandle::into_inner(self_);
+            inner.0.borrow_mut().push((String::from("movable"), 0));
+        }
+    }
+
+    #[test]
+    fn pinned() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut node = Box::pin(Node::from(NodeInner {
+            name: String::from("a"),
+            log: log.clone(),
+            _pin: PhantomPinned,
+        }));
+
+        let address = &*node.as_mut().as_pin_mut() as *const NodeInner as usize;
+        mem::drop(node);
+        assert_eq!(*log.borrow(), [(String::from("a"), address)]);
+        assert_eq!(Rc::strong_count(&log), 1);
+    }
+
+    #[test]
+    fn unpinned() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let node = Node::from(NodeInner {
+            name: String::from("b"),
+            log: log.clone(),
+            _pin: PhantomPinned,
+        });
+
+        let inner: NodeInner = node.into();
+        assert_eq!(inner.name, "b");
+        mem::drop(inner);
+        assert!(log.borrow().is_empty());
+
+        mem::drop(Movable::from(MovableInner(log.clone())));
+        assert_eq!(*log.borrow(), [(String::from("movable"), 0)]);
+        assert_eq!(Rc::strong_count(&log), 1);
+    }
+
+    drop_move_wrap! {
+        #[drop_move(pin)]
+        pub struct Generic<'a, T>(GenericInner {
+            value: &'a mut T,
+            copy: T,
+        }) where T: Clone;
+    }
+
+    impl<'a, T: Clone> PinDropMove for GenericInner<'a, T> {}
+
+    fn assert_unpin<T: Unpin>() {}
+
+    #[test]
+    fn unpin() {
+        assert_unpin::<Movable>();
+        assert_unpin::<Generic<u32>>();
+        assert_unpin::<GenericInner<u32>>();
+
+        let mut value = 1;
+        let mut generic = Generic::from(GenericInner {
+            value: &mut value,
+            copy: 2,
+        });
+        let inner = Pin::new(&mut generic).as_pin_mut();
+        **inner.value().get_mut() += 2;
+        mem::drop(generic);
+        assert_eq!(value, 3);
+    }
+}
@@ -0,0 +1,444 @@
+//! Object pools, which return their values to the pool when they are dropped.
+//!
+//! A [`Pool`] is single threaded and stores its free values in a [`Vec`], while a [`SyncPool`]
+//! can be shared between threads and stores them in a fixed number of lock-free slots. Both take
+//! a capacity, beyond which returned values are dropped, and an optional reset hook that is run on
+//! each value before it is returned to the pool.
+//!
+//! ```
+//! use drop_move::pool::Pool;
+//!
+//! let pool = Pool::with_reset(4, |buffer: &mut Vec<u8>| buffer.clear());
+//!
+//! let mut buffer = pool.get_or_else(Vec::new);
+//! buffer.extend_from_slice(b"hello");
+//! drop(buffer);
+//!
+//! let buffer = pool.get().unwrap();
+//! assert!(buffer.is_empty());
+//! assert!(buffer.capacity() >= 5);
+//! ```
+//!
+//! Requires the `alloc` feature.
+
+use super::*;
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+/// A single threaded pool of values of type `T`. Cloning the pool gives another reference to the
+/// same pool.
+pub struct Pool<T>(Rc<PoolShared<T>>);
+
+type Reset<T> = Box<dyn Fn(&mut T)>;
+type SyncReset<T> = Box<dyn Fn(&mut T) + Send + Sync>;
+
+struct PoolShared<T> {
+    free: RefCell<Vec<T>>,
+    capacity: usize,
+    reset: Option<Reset<T>>,
+}
+
+impl<T> Pool<T> {
+    /// Create an empty pool that holds at most `capacity` free values.
+    pub fn new(capacity: usize) -> Self {
+        Self::from_shared(capacity, None)
+    }
+
+    /// Create an empty pool that holds at most `capacity` free values, and runs `reset` on each
+    /// value before it is returned to the pool.
+    pub fn with_reset(capacity: usize, reset: impl Fn(&mut T) + 'static) -> Self {
+        Self::from_shared(capacity, Some(Box::new(reset)))
+    }
+
+    fn from_shared(capacity: usize, reset: Option<Reset<T>>) -> Self {
+        Pool(Rc::new(PoolShared {
+            free: RefCell::new(Vec::new()),
+            capacity,
+            reset,
+        }))
+    }
+
+    /// Take a free value from the pool, if there is one.
+    pub fn get(&self) -> Option<Pooled<T>> {
+        let value = self.0.free.borrow_mut().pop()?;
+        Some(self.attach(value))
+    }
+
+    /// Take a free value from the pool, or create a new one with `f` if it is empty.
+    pub fn get_or_else(&self, f: impl FnOnce() -> T) -> Pooled<T> {
+        self.get().unwrap_or_else(|| self.attach(f()))
+    }
+
+    /// Wrap a value so that it will be returned to this pool when it is dropped.
+    pub fn attach(&self, value: T) -> Pooled<T> {
+        Pooled::from(PooledInner {
+            value,
+            pool: self.clone(),
+        })
+    }
+
+    /// Reset a value and add it to the pool, or drop it if the pool is full.
+    pub fn put(&self, mut value: T) {
+        if self.len() >= self.0.capacity {
+            return;
+        }
+
+        if let Some(reset) = &self.0.reset {
+            reset(&mut value);
+        }
+
+        let mut free = self.0.free.borrow_mut();
+        if free.len() < self.0.capacity {
+            free.push(value);
+        }
+    }
+
+    /// The number of free values in the pool.
+    pub fn len(&self) -> usize {
+        self.0.free.borrow().len()
+    }
+
+    /// Check whether there are no free values in the pool.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The maximum number of free values that the pool will hold.
+    pub fn capacity(&self) -> usize {
+        self.0.capacity
+    }
+}
+
+impl<T> Clone for Pool<T> {
+    fn clone(&self) -> Self {
+        Pool(self.0.clone())
+    }
+}
+
+impl<T> fmt::Debug for Pool<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Pool")
+            .field("len", &self.len())
+            .field("capacity", &self.0.capacity)
+            .finish()
+    }
+}
+
+drop_move_wrap! {
+    /// A value borrowed from a [`Pool`], which is returned to the pool when dropped.
+    pub struct Pooled<T>(PooledInner {
+        value: T,
+        pool: Pool<T>,
+    });
+}
+
+impl<T> DropMove for PooledInner<T> {
+    fn drop_move(self_: DropHandle<Self>) {
+        let inner = DropHandle::into_inner(self_);
+        inner.pool.put(inner.value);
+    }
+}
+
+impl<T> Pooled<T> {
+    /// Take the value, so that it will not be returned to the pool.
+    ///
+    /// This is an associated function so that will not conflict with any methods of `T`, which
+    /// are accessible through [`Deref`].
+    pub fn detach(self_: Self) -> T {
+        PooledInner::from(self_).value
+    }
+
+    /// The pool that the value will be returned to.
+    pub fn pool(self_: &Self) -> &Pool<T> {
+        &self_.0.pool
+    }
+}
+
+impl<T> Deref for Pooled<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.0.value
+    }
+}
+
+impl<T> DerefMut for Pooled<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0.value
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Pooled<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Pooled").field(&self.0.value).finish()
+    }
+}
+
+/// A thread safe pool of values of type `T`. Cloning the pool gives another reference to the same
+/// pool.
+///
+/// Values are kept boxed for their whole lifetime, so that returning them to the pool never
+/// allocates. The free values are stored in `capacity` slots, each of which is a single atomic
+/// pointer, so taking and returning values is lock-free but takes time linear in the capacity.
+pub struct SyncPool<T>(Arc<SyncPoolShared<T>>);
+
+struct SyncPoolShared<T> {
+    slots: Box<[AtomicPtr<T>]>,
+    reset: Option<SyncReset<T>>,
+    boxes: PhantomData<Box<T>>,
+}
+
+// Values are only ever moved through the pool, never shared.
+unsafe impl<T: Send> Send for SyncPoolShared<T> {}
+unsafe impl<T: Send> Sync for SyncPoolShared<T> {}
+
+impl<T> SyncPool<T> {
+    /// Create an empty pool that holds at most `capacity` free values.
+    pub fn new(capacity: usize) -> Self {
+        Self::from_shared(capacity, None)
+    }
+
+    /// Create an empty pool that holds at most `capacity` free values, and runs `reset` on each
+    /// value before it is returned to the pool.
+    pub fn with_reset(capacity: usize, reset: impl Fn(&mut T) + Send + Sync + 'static) -> Self {
+        Self::from_shared(capacity, Some(Box::new(reset)))
+    }
+
+    fn from_shared(capacity: usize, reset: Option<SyncReset<T>>) -> Self {
+        SyncPool(Arc::new(SyncPoolShared {
+            slots: (0..capacity)
+                .map(|_| AtomicPtr::new(ptr::null_mut()))
+                .collect(),
+            reset,
+            boxes: PhantomData,
+        }))
+    }
+
+    /// Take a free value from the pool, if there is one.
+    pub fn get(&self) -> Option<SyncPooled<T>> {
+        for slot in self.0.slots.iter() {
+            if slot.load(Ordering::Relaxed).is_null() {
+                continue;
+            }
+
+            let value = slot.swap(ptr::null_mut(), Ordering::Acquire);
+            if !value.is_null() {
+                // Safety: the pointer came from Box::into_raw in put_boxed, and the swap means that
+                // nothing else can take it.
+                return Some(self.attach_boxed(unsafe { Box::from_raw(value) }));
+            }
+        }
+        None
+    }
+
+    /// Take a free value from the pool, or create a new one with `f` if it is empty.
+    pub fn get_or_else(&self, f: impl FnOnce() -> T) -> SyncPooled<T> {
+        self.get().unwrap_or_else(|| self.attach(f()))
+    }
+
+    /// Wrap a value so that it will be returned to this pool when it is dropped.
+    pub fn attach(&self, value: T) -> SyncPooled<T> {
+        self.attach_boxed(Box::new(value))
+    }
+
+    /// Wrap a boxed value so that it will be returned to this pool when it is dropped.
+    pub fn attach_boxed(&self, value: Box<T>) -> SyncPooled<T> {
+        SyncPooled::from(SyncPooledInner {
+            value,
+            pool: self.clone(),
+        })
+    }
+
+    /// Reset a value and add it to the pool, or drop it if the pool is full.
+    pub fn put(&self, value: T) {
+        self.put_boxed(Box::new(value))
+    }
+
+    /// Reset a boxed value and add it to the pool, or drop it if the pool is full.
+    pub fn put_boxed(&self, mut value: Box<T>) {
+        if self.len() >= self.capacity() {
+            return;
+        }
+
+        if let Some(reset) = &self.0.reset {
+            reset(&mut value);
+        }
+
+        let value = Box::into_raw(value);
+        for slot in self.0.slots.iter() {
+            if slot.load(Ordering::Relaxed).is_null()
+                && slot
+                    .compare_exchange(ptr::null_mut(), value, Ordering::Release, Ordering::Relaxed)
+                    .is_ok()
+            {
+                return;
+            }
+        }
+
+        // Another thread filled the pool first.
+        mem::drop(unsafe { Box::from_raw(value) });
+    }
+
+    /// The number of free values in the pool. This may be out of date by the time it returns, if
+    /// other threads are using the pool.
+    pub fn len(&self) -> usize {
+        self.0
+            .slots
+            .iter()
+            .filter(|slot| !slot.load(Ordering::Relaxed).is_null())
+            .count()
+    }
+
+    /// Check whether there are no free values in the pool.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The maximum number of free values that the pool will hold.
+    pub fn capacity(&self) -> usize {
+        self.0.slots.len()
+    }
+}
+
+impl<T> Clone for SyncPool<T> {
+    fn clone(&self) -> Self {
+        SyncPool(self.0.clone())
+    }
+}
+
+impl<T> fmt::Debug for SyncPool<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SyncPool")
+            .field("len", &self.len())
+            .field("capacity", &self.capacity())
+            .finish()
+    }
+}
+
+impl<T> Drop for SyncPoolShared<T> {
+    fn drop(&mut self) {
+        for slot in self.slots.iter_mut() {
+            let value = *slot.get_mut();
+            if !value.is_null() {
+                mem::drop(unsafe { Box::from_raw(value) });
+            }
+        }
+    }
+}
+
+drop_move_wrap! {
+    /// A value borrowed from a [`SyncPool`], which is returned to the pool when dropped.
+    pub struct SyncPooled<T>(SyncPooledInner {
+        value: Box<T>,
+        pool: SyncPool<T>,
+    });
+}
+
+impl<T> DropMove for SyncPooledInner<T> {
+    fn drop_move(self_: DropHandle<Self>) {
+        let inner = DropHandle::into_inner(self_);
+        inner.pool.put_boxed(inner.value);
+    }
+}
+
+impl<T> SyncPooled<T> {
+    /// Take the value, so that it will not be returned to the pool.
+    ///
+    /// This is an associated function so that will not conflict with any methods of `T`, which
+    /// are accessible through [`Deref`].
+    pub fn detach(self_: Self) -> T {
+        *Self::detach_boxed(self_)
+    }
+
+    /// Take the boxed value, so that it will not be returned to the pool.
+    pub fn detach_boxed(self_: Self) -> Box<T> {
+        SyncPooledInner::from(self_).value
+    }
+
+    /// The pool that the value will be returned to.
+    pub fn pool(self_: &Self) -> &SyncPool<T> {
+        &self_.0.pool
+    }
+}
+
+impl<T> Deref for SyncPooled<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.0.value
+    }
+}
+
+impl<T> DerefMut for SyncPooled<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0.value
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for SyncPooled<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("SyncPooled").field(&self.0.value).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    extern crate std;
+    use std::thread;
+    use std::vec;
+
+    #[test]
+    fn pool() {
+        let pool = Pool::with_reset(2, |x: &mut Vec<u32>| x.clear());
+        assert!(pool.get().is_none());
+
+        let mut a = pool.get_or_else(Vec::new);
+        let b = pool.attach(vec![2]);
+        let c = pool.attach(vec![3]);
+        a.push(1);
+        assert_eq!(*a, [1]);
+
+        mem::drop(a);
+        mem::drop(b);
+        mem::drop(c);
+        assert_eq!(pool.len(), 2);
+
+        let a = pool.get().unwrap();
+        assert!(a.is_empty());
+        assert!(a.capacity() >= 1);
+        assert_eq!(Pooled::detach(a), []);
+        assert_eq!(pool.len(), 1);
+    }
+
+    #[test]
+    fn sync_pool() {
+        let pool = SyncPool::with_reset(4, |x: &mut u32| *x = 0);
+        assert_eq!(pool.capacity(), 4);
+
+        thread::scope(|scope| {
+            for i in 0..8 {
+                let pool = &pool;
+                scope.spawn(move || {
+                    for _ in 0..100 {
+                        let mut x = pool.get_or_else(|| 0);
+                        assert_eq!(*x, 0);
+                        *x = i;
+                    }
+                });
+            }
+        });
+
+        assert!(pool.len() <= 4);
+        assert!(!pool.is_empty());
+        let x = pool.get().unwrap();
+        assert!(Arc::ptr_eq(&SyncPooled::pool(&x).0, &pool.0));
+        assert_eq!(SyncPooled::detach(x), 0);
+    }
+}
@@ -0,0 +1,400 @@
+//! Checks that every way of converting, cloning, leaking, and dropping the wrappers and handles
+//! drops each member exactly once. These avoid anything that Miri cannot run, so the unsafe code
+//! can be checked with `cargo miri test --all-features soundness`.
+
+// Most fields are only there to be dropped.
+#![allow(dead_code)]
+
+use super::*;
+
+extern crate std;
+use core::cell::Cell;
+use std::boxed::Box;
+use std::rc::Rc;
+
+/// Counts how many times it, or any clone of it, has been dropped.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct Counted(Rc<Cell<u32>>);
+
+impl core::hash::Hash for Counted {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.0.get().hash(state);
+    }
+}
+
+impl Counted {
+    fn drops(&self) -> u32 {
+        self.0.get()
+    }
+}
+
+impl Drop for Counted {
+    fn drop(&mut self) {
+        self.0.set(self.0.get() + 1);
+    }
+}
+
+drop_move_wrap! {
+    #[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    struct Plain(PlainInner {
+        field: Counted,
+        calls: Counted,
+    });
+}
+
+impl DropMove for PlainInner {
+    fn drop_move(self_: DropHandle<Self>) {
+        mem::drop(self_.0.calls.clone());
+    }
+}
+
+drop_move_wrap! {
+    #[drop_move(cancelable)]
+    struct Cancelable(CancelableInner {
+        field: Counted,
+        calls: Counted,
+    });
+}
+
+impl DropMove for CancelableInner {
+    fn drop_move(self_: DropHandle<Self>) {
+        mem::drop(self_.0.calls.clone());
+    }
+}
+
+drop_move_wrap! {
+    #[drop_move(pin)]
+    struct Pinned(PinnedInner {
+        field: Counted,
+        calls: Counted,
+    });
+}
+
+impl PinDropMove for PinnedInner {
+    fn drop_move_pinned(self_: PinDropHandle<Self>) {
+        mem::drop(self_.calls.clone());
+    }
+}
+
+fn counters() -> (Counted, Counted) {
+    (Counted::default(), Counted::default())
+}
+
+/// The drops of `field`, and of `calls`, which is dropped once more for each call to `drop_move`.
+fn counts(field: &Counted, calls: &Counted) -> (u32, u32) {
+    (field.drops(), calls.drops())
+}
+
+fn plain(field: &Counted, calls: &Counted) -> Plain {
+    Plain::from(PlainInner {
+        field: Counted(field.0.clone()),
+        calls: Counted(calls.0.clone()),
+    })
+}
+
+#[test]
+fn drop_once() {
+    let (field, calls) = counters();
+    mem::drop(plain(&field, &calls));
+    assert_eq!(counts(&field, &calls), (1, 2));
+
+    let (field, calls) = counters();
+    mem::drop(Cancelable::from(CancelableInner {
+        field: Counted(field.0.clone()),
+        calls: Counted(calls.0.clone()),
+    }));
+    assert_eq!(counts(&field, &calls), (1, 2));
+
+    let (field, calls) = counters();
+    mem::drop(Pinned::from(PinnedInner {
+        field: Counted(field.0.clone()),
+        calls: Counted(calls.0.clone()),
+    }));
+    assert_eq!(counts(&field, &calls), (1, 2));
+}
+
+#[test]
+fn into_inner() {
+    let (field, calls) = counters();
+    mem::drop(PlainInner::from(plain(&field, &calls)));
+    mem::drop(Plain::defuse(plain(&field, &calls)));
+    assert_eq!(counts(&field, &calls), (2, 2));
+
+    let (field, calls) = counters();
+    let mut cancelable = Cancelable::from(CancelableInner {
+        field: Counted(field.0.clone()),
+        calls: Counted(calls.0.clone()),
+    });
+    Cancelable::cancel(&mut cancelable);
+    mem::drop(cancelable);
+    assert_eq!(counts(&field, &calls), (1, 1));
+
+    let (field, calls) = counters();
+    let pinned = Pinned::from(PinnedInner {
+        field: Counted(field.0.clone()),
+        calls: Counted(calls.0.clone()),
+    });
+    mem::drop(PinDropMoveWrapper::into_inner(pinned.0));
+    assert_eq!(counts(&field, &calls), (1, 1));
+}
+
+drop_move_wrap! {
+    struct Handles(HandlesInner {
+        field: Counted,
+        mode: u8,
+    });
+}
+
+impl DropMove for HandlesInner {
+    fn drop_move(self_: DropHandle<Self>) {
+        match self_.0.mode {
+            0 => mem::drop(DropHandle::into_inner(self_)),
+            1 => {
+                let outer = DropHandle::into_outer(self_);
+                mem::drop(HandlesInner::from(outer));
+            }
+            2 => mem::forget(self_),
+            _ => mem::drop(self_),
+        }
+    }
+}
+
+#[test]
+fn handles() {
+    let field = Counted::default();
+    for mode in 0..4 {
+        mem::drop(Handles::from(HandlesInner {
+            field: Counted(field.0.clone()),
+            mode,
+        }));
+    }
+
+    // The forgotten handle leaks its field.
+    assert_eq!(field.drops(), 3);
+    assert_eq!(Rc::strong_count(&field.0), 2);
+    release_leaked(&field, 1);
+}
+
+drop_move_wrap! {
+    #[drop_move(pin)]
+    struct PinnedHandles(PinnedHandlesInner {
+        field: Counted,
+        mode: u8,
+    });
+}
+
+impl PinDropMove for PinnedHandlesInner {
+    #[allow(clippy::forget_non_drop)]
+    fn drop_move_pinned(self_: PinDropHandle<Self>) {
+        match self_.mode {
+            0 => mem::drop(PinDropHandle::into_inner(self_)),
+            1 => mem::forget(self_),
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn pinned_handles() {
+    // The structure is dropped in place even if the handle is forgotten, as its memory is about to
+    // be reused.
+    let field = Counted::default();
+    for mode in 0..3 {
+        let pinned = Box::pin(PinnedHandles::from(PinnedHandlesInner {
+            field: Counted(field.0.clone()),
+            mode,
+        }));
+        mem::drop(pinned);
+    }
+
+    assert_eq!(field.drops(), 3);
+    assert_eq!(Rc::strong_count(&field.0), 1);
+}
+
+/// Give back references that were leaked on purpose, so that Miri does not report them.
+fn release_leaked(x: &Counted, n: usize) {
+    for _ in 0..n {
+        unsafe { Rc::decrement_strong_count(Rc::as_ptr(&x.0)) };
+    }
+}
+
+#[test]
+fn leak() {
+    let (field, calls) = counters();
+    mem::forget(plain(&field, &calls));
+    mem::forget(DropHandle::from(PlainInner {
+        field: Counted(field.0.clone()),
+        calls: Counted(calls.0.clone()),
+    }));
+    assert_eq!(counts(&field, &calls), (0, 0));
+    assert_eq!(Rc::strong_count(&field.0), 3);
+    release_leaked(&field, 2);
+    release_leaked(&calls, 2);
+}
+
+#[test]
+fn clone() {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::Hash;
+
+    let (field, calls) = counters();
+    let a = plain(&field, &calls);
+    let b = a.clone();
+    assert_eq!(a, b);
+    assert_eq!(a.cmp(&b), core::cmp::Ordering::Equal);
+    a.hash(&mut DefaultHasher::new());
+    assert_eq!(counts(&field, &calls), (0, 0));
+
+    mem::drop(a);
+    mem::drop(b);
+    assert_eq!(counts(&field, &calls), (2, 4));
+
+    mem::drop(Plain::default());
+}
+
+#[cfg(feature = "std")]
+mod panics {
+    use super::*;
+    use std::panic::{self, AssertUnwindSafe};
+
+    std::thread_local! {
+        static PANIC_INTO_INNER: Cell<bool> = const { Cell::new(false) };
+        static PANIC_INTO_OUTER: Cell<bool> = const { Cell::new(false) };
+    }
+
+    fn maybe_panic(flag: &'static std::thread::LocalKey<Cell<bool>>) {
+        if flag.with(|flag| flag.replace(false)) {
+            panic!("conversion failed");
+        }
+    }
+
+    /// Implemented by hand, with conversions that can be made to panic once.
+    struct Fragile(DropMoveWrapper<FragileInner>);
+
+    struct FragileInner {
+        field: Counted,
+        calls: Counted,
+        into_inner: bool,
+    }
+
+    impl From<Fragile> for FragileInner {
+        fn from(x: Fragile) -> Self {
+            maybe_panic(&PANIC_INTO_INNER);
+            DropMoveWrapper::into_inner(x.0)
+        }
+    }
+
+    impl From<FragileInner> for Fragile {
+        fn from(x: FragileInner) -> Self {
+            maybe_panic(&PANIC_INTO_OUTER);
+            Fragile(DropMoveWrapper::new(x))
+        }
+    }
+
+    impl DropMoveTypes for FragileInner {
+        type Outer = Fragile;
+    }
+
+    impl DropMove for FragileInner {
+        fn drop_move(self_: DropHandle<Self>) {
+            mem::drop(self_.0.calls.clone());
+            if self_.0.into_inner {
+                mem::drop(DropHandle::into_inner(self_));
+            }
+        }
+    }
+
+    fn fragile(field: &Counted, calls: &Counted, into_inner: bool) -> Fragile {
+        Fragile(DropMoveWrapper::new(FragileInner {
+            field: Counted(field.0.clone()),
+            calls: Counted(calls.0.clone()),
+            into_inner,
+        }))
+    }
+
+    fn drop_panicking<T>(x: T) {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| mem::drop(x)));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn panic_in_into() {
+        // Converting the handle to the inner structure fails, so the outer structure is dropped
+        // while unwinding, without calling `drop_move` a second time.
+        for into_inner in [false, true] {
+            let (field, calls) = counters();
+            let x = fragile(&field, &calls, into_inner);
+            PANIC_INTO_INNER.with(|flag| flag.set(true));
+            drop_panicking(x);
+            assert_eq!(counts(&field, &calls), (1, 2));
+        }
+
+        // Other values of the same type that are dropped by the same panic still call `drop_move`.
+        let (field, calls) = counters();
+        let (other_field, other_calls) = counters();
+        let x = fragile(&field, &calls, false);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _other = fragile(&other_field, &other_calls, false);
+            PANIC_INTO_INNER.with(|flag| flag.set(true));
+            mem::drop(x);
+        }));
+        assert!(result.is_err());
+        assert_eq!(counts(&field, &calls), (1, 2));
+        assert_eq!(counts(&other_field, &other_calls), (1, 2));
+
+        // Creating the handle fails, so the inner structure is dropped without `drop_move`.
+        let (field, calls) = counters();
+        let x = fragile(&field, &calls, false);
+        PANIC_INTO_OUTER.with(|flag| flag.set(true));
+        drop_panicking(x);
+        assert_eq!(counts(&field, &calls), (1, 1));
+    }
+
+    drop_move_wrap! {
+        #[drop_move(fields)]
+        struct Partial(PartialInner {
+            moved: Counted,
+            kept: Counted,
+        });
+    }
+
+    impl DropMove for PartialInner {
+        fn drop_move(self_: DropHandle<Self>) {
+            let mut handle = PartialHandle::new(self_);
+            let _moved = PartialHandle::fields(&mut handle).moved.take();
+            panic!("drop_move failed");
+        }
+    }
+
+    #[test]
+    fn panic_in_drop_move() {
+        let (moved, kept) = counters();
+        drop_panicking(Partial::from(PartialInner {
+            moved: Counted(moved.0.clone()),
+            kept: Counted(kept.0.clone()),
+        }));
+        assert_eq!(counts(&moved, &kept), (1, 1));
+    }
+
+    drop_move_wrap! {
+        #[drop_move(pin)]
+        struct PinnedPanic(PinnedPanicInner(Counted));
+    }
+
+    impl PinDropMove for PinnedPanicInner {
+        #[allow(clippy::forget_non_drop)]
+        fn drop_move_pinned(self_: PinDropHandle<Self>) {
+            mem::forget(self_);
+            panic!("drop_move_pinned failed");
+        }
+    }
+
+    #[test]
+    fn panic_in_drop_move_pinned() {
+        let field = Counted::default();
+        drop_panicking(Box::pin(PinnedPanic::from(PinnedPanicInner(Counted(
+            field.0.clone(),
+        )))));
+        assert_eq!(field.drops(), 1);
+    }
+}
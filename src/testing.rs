@@ -0,0 +1,371 @@
+//! Helpers for testing [`DropMove`](crate::DropMove) implementations.
+//!
+//! A [`DropTracker`] hands out [`Tracked`] values, each labeled with a unique key, and records
+//! when they are dropped. Put them inside the structures being tested, and give the
+//! `tracked(field)` option to [`drop_move_wrap!`](crate::drop_move_wrap) so that each call to
+//! `drop_move` is recorded with the value in `field`, then check the recorded events with the
+//! assertion helpers.
+//!
+//! ```
+//! use drop_move::testing::{DropTracker, Tracked};
+//! use drop_move::{drop_move_wrap, DropHandle, DropMove};
+//!
+//! drop_move_wrap! {
+//!     #[drop_move(tracked(tracked))]
+//!     pub struct Guard<'a>(GuardInner {
+//!         tracked: Tracked<'a>,
+//!     });
+//! }
+//!
+//! impl<'a> DropMove for GuardInner<'a> {
+//!     fn drop_move(self_: DropHandle<Self>) {
+//!         // Release the guard.
+//!     }
+//! }
+//!
+//! let tracker = DropTracker::new();
+//! {
+//!     let _a = Guard::from(GuardInner { tracked: tracker.track("a") });
+//!     let _b = tracker.track("b");
+//! }
+//! tracker.assert_drop_moved_once(&"a");
+//! tracker.assert_drop_order(&["b", "a"]);
+//! tracker.assert_no_leaks();
+//! ```
+//!
+//! The calls are recorded by the wrappers, just before they call `drop_move`, so a `drop_move`
+//! that is skipped or called twice shows up in [`DropTracker::drop_move_count`]. For structures
+//! that are not generated by the macro, [`Tracked::mark_drop_move`] can be called by hand instead.
+//!
+//! Requires the `testing` feature.
+
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt::Debug;
+
+/// Something that happened to a [`Tracked`] value.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DropEvent<K> {
+    /// `drop_move` was called for the structure containing the value.
+    DropMove(K),
+    /// The value was dropped.
+    Drop(K),
+}
+
+/// Records the drops of the [`Tracked`] values that it hands out. The keys `K` label the values,
+/// and must be unique.
+#[derive(Debug)]
+pub struct DropTracker<K = &'static str> {
+    keys: RefCell<Vec<K>>,
+    events: RefCell<Vec<(usize, DropEvent<K>)>>,
+}
+
+impl<K: Clone + Debug + PartialEq> DropTracker<K> {
+    /// Create a tracker with no values.
+    pub fn new() -> Self {
+        DropTracker {
+            keys: RefCell::new(Vec::new()),
+            events: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Create a new tracked value labeled with `key`.
+    ///
+    /// Panics if `key` has already been used.
+    pub fn track(&self, key: K) -> Tracked<'_, K> {
+        let mut keys = self.keys.borrow_mut();
+        assert!(!keys.contains(&key), "key {:?} is already tracked", key);
+        keys.push(key);
+        Tracked {
+            tracker: self,
+            index: keys.len() - 1,
+        }
+    }
+
+    fn record(&self, index: usize, event: fn(K) -> DropEvent<K>) {
+        let key = self.keys.borrow()[index].clone();
+        self.events.borrow_mut().push((index, event(key)));
+    }
+
+    fn index(&self, key: &K) -> usize {
+        self.keys
+            .borrow()
+            .iter()
+            .position(|k| k == key)
+            .unwrap_or_else(|| panic!("key {:?} is not tracked", key))
+    }
+
+    fn count(&self, key: &K, event: fn(K) -> DropEvent<K>) -> usize {
+        let index = self.index(key);
+        let event = event(key.clone());
+        self.events
+            .borrow()
+            .iter()
+            .filter(|(i, e)| *i == index && *e == event)
+            .count()
+    }
+
+    /// All events so far, in the order they happened.
+    pub fn events(&self) -> Vec<DropEvent<K>> {
+        self.events
+            .borrow()
+            .iter()
+            .map(|(_, e)| e.clone())
+            .collect()
+    }
+
+    /// The number of times that the value labeled `key` has been dropped.
+    pub fn drop_count(&self, key: &K) -> usize {
+        self.count(key, DropEvent::Drop)
+    }
+
+    /// The number of times that `drop_move` has been called for the structure containing the value
+    /// labeled `key`.
+    pub fn drop_move_count(&self, key: &K) -> usize {
+        self.count(key, DropEvent::DropMove)
+    }
+
+    /// The keys of the values that have been dropped, in order.
+    pub fn drop_order(&self) -> Vec<K> {
+        self.events
+            .borrow()
+            .iter()
+            .filter_map(|(_, e)| match e {
+                DropEvent::Drop(key) => Some(key.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The keys of the values that have not been dropped yet.
+    pub fn leaked(&self) -> Vec<K> {
+        self.keys
+            .borrow()
+            .iter()
+            .filter(|key| self.drop_count(key) == 0)
+            .cloned()
+            .collect()
+    }
+
+    /// The keys of the values that have been dropped more than once.
+    pub fn double_dropped(&self) -> Vec<K> {
+        self.keys
+            .borrow()
+            .iter()
+            .filter(|key| self.drop_count(key) > 1)
+            .cloned()
+            .collect()
+    }
+
+    /// Assert that the value labeled `key` has been dropped exactly once.
+    #[track_caller]
+    pub fn assert_dropped_once(&self, key: &K) {
+        let count = self.drop_count(key);
+        assert!(
+            count == 1,
+            "expected {:?} to be dropped once, but it was dropped {} times. Events: {:?}",
+            key,
+            count,
+            self.events()
+        );
+    }
+
+    /// Assert that `drop_move` has been called exactly once for the structure containing the value
+    /// labeled `key`, before the value was dropped.
+    #[track_caller]
+    pub fn assert_drop_moved_once(&self, key: &K) {
+        let count = self.drop_move_count(key);
+        assert!(
+            count == 1,
+            "expected drop_move to be called once for {:?}, but it was called {} times. \
+             Events: {:?}",
+            key,
+            count,
+            self.events()
+        );
+
+        let events = self.events();
+        let drop_move = events
+            .iter()
+            .position(|e| *e == DropEvent::DropMove(key.clone()));
+        let drop = events
+            .iter()
+            .position(|e| *e == DropEvent::Drop(key.clone()));
+        assert!(
+            drop.is_none() || drop > drop_move,
+            "expected drop_move to be called for {:?} before it was dropped. Events: {:?}",
+            key,
+            events
+        );
+    }
+
+    /// Assert that the values were dropped in exactly the order given by `keys`.
+    #[track_caller]
+    pub fn assert_drop_order(&self, keys: &[K]) {
+        let order = self.drop_order();
+        assert!(
+            order == keys,
+            "expected drop order {:?}, but got {:?}",
+            keys,
+            order
+        );
+    }
+
+    /// Assert that every value has been dropped.
+    #[track_caller]
+    pub fn assert_no_leaks(&self) {
+        let leaked = self.leaked();
+        assert!(leaked.is_empty(), "leaked {:?}", leaked);
+    }
+
+    /// Assert that no value has been dropped more than once.
+    #[track_caller]
+    pub fn assert_no_double_drops(&self) {
+        let double_dropped = self.double_dropped();
+        assert!(
+            double_dropped.is_empty(),
+            "dropped more than once: {:?}",
+            double_dropped
+        );
+    }
+}
+
+impl<K: Clone + Debug + PartialEq> Default for DropTracker<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A value handed out by a [`DropTracker`], which records when it is dropped.
+///
+/// This only holds a reference to the tracker, so a double drop is recorded rather than causing
+/// any further undefined behavior.
+#[derive(Debug)]
+pub struct Tracked<'a, K: Clone + Debug + PartialEq = &'static str> {
+    tracker: &'a DropTracker<K>,
+    index: usize,
+}
+
+impl<'a, K: Clone + Debug + PartialEq> Tracked<'a, K> {
+    /// The key that labels this value.
+    pub fn key(&self) -> K {
+        self.tracker.keys.borrow()[self.index].clone()
+    }
+
+    /// Record that `drop_move` was called for the structure containing this value. This is called
+    /// automatically for structures with the `tracked` option, so it is only needed for others.
+    pub fn mark_drop_move(&self) {
+        self.tracker.record(self.index, DropEvent::DropMove);
+    }
+}
+
+impl<'a, K: Clone + Debug + PartialEq> Drop for Tracked<'a, K> {
+    fn drop(&mut self) {
+        self.tracker.record(self.index, DropEvent::Drop);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    extern crate std;
+    use core::ptr;
+
+    drop_move_wrap! {
+        #[drop_move(tracked(first))]
+        pub struct Pair<'a>(PairInner {
+            first: Tracked<'a, u32>,
+            second: Tracked<'a, u32>,
+        });
+    }
+
+    impl<'a> DropMove for PairInner<'a> {
+        fn drop_move(self_: DropHandle<Self>) {
+            let inner = DropHandle::into_inner(self_);
+            mem::drop(inner.second);
+        }
+    }
+
+    drop_move_wrap! {
+        #[drop_move(cancelable, tracked(0))]
+        pub struct Single<'a>(SingleInner(Tracked<'a>));
+    }
+
+    impl<'a> DropMove for SingleInner<'a> {}
+
+    #[test]
+    fn tracker() {
+        let tracker = DropTracker::new();
+        {
+            let _pair = Pair::from(PairInner {
+                first: tracker.track(1),
+                second: tracker.track(2),
+            });
+            let three = tracker.track(3);
+            assert_eq!(three.key(), 3);
+        }
+
+        tracker.assert_drop_moved_once(&1);
+        tracker.assert_drop_order(&[3, 2, 1]);
+        tracker.assert_dropped_once(&2);
+        tracker.assert_no_leaks();
+        tracker.assert_no_double_drops();
+        assert_eq!(
+            tracker.events(),
+            [
+                DropEvent::Drop(3),
+                DropEvent::DropMove(1),
+                DropEvent::Drop(2),
+                DropEvent::Drop(1)
+            ]
+        );
+    }
+
+    #[test]
+    fn skipped_drop_move() {
+        let tracker = DropTracker::new();
+        mem::drop(Single::from(SingleInner(tracker.track("called"))));
+        let mut canceled = Single::from(SingleInner(tracker.track("canceled")));
+        Single::cancel(&mut canceled);
+        mem::drop(canceled);
+
+        tracker.assert_drop_moved_once(&"called");
+        assert_eq!(tracker.drop_move_count(&"canceled"), 0);
+        tracker.assert_no_leaks();
+    }
+
+    #[test]
+    fn leaks_and_double_drops() {
+        let tracker = DropTracker::new();
+        mem::forget(tracker.track("leaked"));
+
+        let mut doubled = mem::ManuallyDrop::new(tracker.track("doubled"));
+        unsafe {
+            mem::drop(ptr::read(&*doubled));
+            mem::ManuallyDrop::drop(&mut doubled);
+        }
+
+        assert_eq!(tracker.leaked(), ["leaked"]);
+        assert_eq!(tracker.double_dropped(), ["doubled"]);
+        assert_eq!(tracker.drop_count(&"doubled"), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected drop order")]
+    fn wrong_order() {
+        let tracker = DropTracker::new();
+        mem::drop(tracker.track("a"));
+        mem::drop(tracker.track("b"));
+        tracker.assert_drop_order(&["b", "a"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "already tracked")]
+    fn duplicate_key() {
+        let tracker = DropTracker::new();
+        let _a = tracker.track("a");
+        let _b = tracker.track("a");
+    }
+}
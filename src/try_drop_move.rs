@@ -0,0 +1,158 @@
+use super::*;
+
+/// A fallible variant of [`DropMove`], for when dropping can fail, e.g. flushing a file or
+/// committing a transaction.
+///
+/// Use the `try_drop` option of [`drop_move_wrap!`] to implement [`DropMove`] in terms of this
+/// trait. This also generates a `close` method on the outer structure, which drops it and returns
+/// the result of [`try_drop_move`](Self::try_drop_move). If the value is instead dropped
+/// implicitly, then any error is passed to [`on_drop_error`](Self::on_drop_error).
+///
+/// ```
+/// use drop_move::{drop_move_wrap, DropHandle, TryDropMove};
+///
+/// drop_move_wrap! {
+///     #[drop_move(try_drop)]
+///     pub struct Transaction(TransactionInner {
+///         fail: bool,
+///     });
+/// }
+///
+/// impl TryDropMove for TransactionInner {
+///     type Error = &'static str;
+///
+///     fn try_drop_move(self_: DropHandle<Self>) -> Result<(), Self::Error> {
+///         if self_.0.fail {
+///             Err("commit failed")
+///         } else {
+///             Ok(())
+///         }
+///     }
+///
+///     fn on_drop_error(error: Self::Error) {
+///         panic!("{}", error);
+///     }
+/// }
+///
+/// let transaction = Transaction::from(TransactionInner { fail: true });
+/// assert_eq!(transaction.close(), Err("commit failed"));
+/// ```
+pub trait TryDropMove: DropMoveTypes {
+    /// The error from a failed drop.
+    type Error;
+
+    /// Drop the value by move, returning whether it succeeded.
+    fn try_drop_move(self_: DropHandle<Self>) -> Result<(), Self::Error>;
+
+    /// Handle an error from [`try_drop_move`](Self::try_drop_move) when the value was dropped
+    /// implicitly, so the error could not be returned. By default the error is ignored, but this
+    /// can be overridden to e.g. panic, log the error, or call a user provided callback.
+    fn on_drop_error(error: Self::Error) {
+        mem::drop(error);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    extern crate std;
+    use core::cell::RefCell;
+    use std::rc::Rc;
+    use std::vec::Vec;
+
+    drop_move_wrap! {
+        #[drop_move(try_drop)]
+        pub struct Writer<'a>(WriterInner {
+            buffer: Vec<u8>,
+            output: &'a RefCell<Vec<u8>>,
+            errors: Rc<RefCell<Vec<usize>>>,
+        });
+    }
+
+    impl<'a> TryDropMove for WriterInner<'a> {
+        type Error = (usize, Rc<RefCell<Vec<usize>>>);
+
+        fn try_drop_move(self_: DropHandle<Self>) -> Result<(), Self::Error> {
+            let inner = DropHandle::into_inner(self_);
+            let mut output = inner.output.borrow_mut();
+            if output.len() + inner.buffer.len() > 4 {
+                return Err((inner.buffer.len(), inner.errors));
+            }
+            output.extend(inner.buffer);
+            Ok(())
+        }
+
+        fn on_drop_error((len, errors): Self::Error) {
+            errors.borrow_mut().push(len);
+        }
+    }
+
+    #[test]
+    fn close() {
+        let output = RefCell::new(Vec::new());
+        let errors = Rc::new(RefCell::new(Vec::new()));
+        let writer = |buffer: &[u8]| {
+            Writer::from(WriterInner {
+                buffer: buffer.into(),
+                output: &output,
+                errors: errors.clone(),
+            })
+        };
+
+        assert!(writer(&[1, 2]).close().is_ok());
+        assert_eq!(writer(&[3, 4, 5]).close().unwrap_err().0, 3);
+        assert_eq!(*output.borrow(), [1, 2]);
+        assert!(errors.borrow().is_empty());
+    }
+
+    #[test]
+    fn implicit_drop() {
+        let output = RefCell::new(Vec::new());
+        let errors = Rc::new(RefCell::new(Vec::new()));
+        let writer = |buffer: &[u8]| {
+            Writer::from(WriterInner {
+                buffer: buffer.into(),
+                output: &output,
+                errors: errors.clone(),
+            })
+        };
+
+        mem::drop(writer(&[1, 2, 3]));
+        mem::drop(writer(&[4, 5]));
+        assert_eq!(*output.borrow(), [1, 2, 3]);
+        assert_eq!(*errors.borrow(), [2]);
+    }
+
+    // The generated code must not be affected by a `Result` alias, as is common in error modules.
+    mod aliased {
+        use super::*;
+
+        type Result<T> = core::result::Result<T, ()>;
+
+        drop_move_wrap! {
+            #[drop_move(try_drop)]
+            pub struct Check(CheckInner(bool));
+        }
+
+        impl TryDropMove for CheckInner {
+            type Error = ();
+
+            fn try_drop_move(self_: DropHandle<Self>) -> Result<()> {
+                if self_.0 .0 {
+                    Ok(())
+                } else {
+                    Err(())
+                }
+            }
+
+            fn on_drop_error(_: ()) {}
+        }
+
+        #[test]
+        fn close() {
+            assert_eq!(Check::from(CheckInner(true)).close(), Ok(()));
+            assert_eq!(Check::from(CheckInner(false)).close(), Err(()));
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use super::*;
+use core::fmt;
+
+/// A [`DropHandle`] for an inner `enum` that is known to hold a particular variant, with fields of
+/// types `F`.
+///
+/// These are created by the `variants(HandleName)` option of [`drop_move_wrap!`], which generates
+/// an `enum HandleName` with a variant holding a `VariantHandle` for each variant of the inner
+/// `enum`, and a [`From`] implementation to convert a [`DropHandle`] into it. Matching on this
+/// gives access to the methods of the outer structure through [`Deref`], before moving out the
+/// fields of only that variant with [`into_fields`](Self::into_fields). The fields are returned as
+/// a tuple, in the order they were declared.
+///
+/// ```
+/// use drop_move::{drop_move_wrap, DropHandle, DropMove, VariantHandle};
+///
+/// drop_move_wrap! {
+///     #[drop_move(variants(ConnectionHandle))]
+///     pub enum Connection(ConnectionInner {
+///         Idle,
+///         Open(u32, String),
+///         Closing { reason: String },
+///     });
+/// }
+///
+/// impl Connection {
+///     fn describe(&self) -> &'static str {
+///         match *self.0 {
+///             ConnectionInner::Idle => "idle",
+///             ConnectionInner::Open(..) => "open",
+///             ConnectionInner::Closing { .. } => "closing",
+///         }
+///     }
+/// }
+///
+/// impl DropMove for ConnectionInner {
+///     fn drop_move(self_: DropHandle<Self>) {
+///         match ConnectionHandle::from(self_) {
+///             ConnectionHandle::Open(handle) => {
+///                 assert_eq!(handle.describe(), "open");
+///                 let (id, peer) = VariantHandle::into_fields(handle);
+///                 // Send a goodbye message to the peer.
+///             }
+///             ConnectionHandle::Closing(handle) => {
+///                 let (reason,) = VariantHandle::into_fields(handle);
+///             }
+///             ConnectionHandle::Idle(_) => {}
+///         }
+///     }
+/// }
+/// ```
+///
+/// Only [`Deref`] is implemented, not [`DerefMut`], as changing the variant would leave the handle
+/// unable to extract its fields. Use [`into_handle`](Self::into_handle) to get mutable access.
+///
+/// Tuple variants may have at most 16 fields.
+pub struct VariantHandle<T: DropMoveTypes, F> {
+    handle: DropHandle<T>,
+    fields: fn(T) -> F,
+}
+
+impl<T: DropMoveTypes, F> VariantHandle<T, F> {
+    /// Wrap a [`DropHandle`], along with a function that extracts the fields of its variant. The
+    /// function may panic if it is given the wrong variant.
+    pub fn new(handle: DropHandle<T>, fields: fn(T) -> F) -> Self {
+        VariantHandle { handle, fields }
+    }
+
+    /// Move out the fields of the variant.
+    ///
+    /// This is an associated function so that will not conflict with any methods of `T::Outer`,
+    /// which are accessible through [`Deref`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the function passed to [`new`](Self::new) does, which for the handles generated
+    /// by `drop_move_wrap!` cannot happen.
+    pub fn into_fields(self_: Self) -> F {
+        (self_.fields)(DropHandle::into_inner(self_.handle))
+    }
+
+    /// Convert back to a [`DropHandle`].
+    pub fn into_handle(self_: Self) -> DropHandle<T> {
+        self_.handle
+    }
+}
+
+impl<T: DropMoveTypes, F> Deref for VariantHandle<T, F> {
+    type Target = T::Outer;
+    fn deref(&self) -> &T::Outer {
+        self.handle.deref()
+    }
+}
+
+impl<T: DropMoveTypes, F> fmt::Debug for VariantHandle<T, F>
+where
+    T::Outer: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("VariantHandle").field(self.deref()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    extern crate std;
+    use core::cell::RefCell;
+    use std::rc::Rc;
+    use std::vec::Vec;
+
+    drop_move_wrap! {
+        #[drop_move(variants(EventHandle))]
+        pub enum Event<T: Into<u32>>(EventInner {
+            Empty,
+            Single(T),
+            Pair(T, T,),
+            Named {
+                first: T,
+                #[allow(dead_code)]
+                second: Rc<RefCell<Vec<T>>>,
+            },
+        });
+    }
+
+    impl<T: Into<u32>> Event<T> {
+        fn tag(&self) -> u32 {
+            match *self.0 {
+                EventInner::Empty => 0,
+                EventInner::Single(_) => 1,
+                EventInner::Pair(..) => 2,
+                EventInner::Named { .. } => 3,
+            }
+        }
+    }
+
+    std::thread_local! {
+        static LOG: RefCell<Vec<(u32, u32)>> = const { RefCell::new(Vec::new()) };
+    }
+
+    impl<T: Into<u32>> DropMove for EventInner<T> {
+        fn drop_move(self_: DropHandle<Self>) {
+            let entry = match EventHandle::from(self_) {
+                EventHandle::Empty(handle) => {
+                    let () = VariantHandle::into_fields(handle);
+                    (0, 0)
+                }
+                EventHandle::Single(handle) => {
+                    let tag = handle.tag();
+                    let (x,) = VariantHandle::into_fields(handle);
+                    (tag, x.into())
+                }
+                EventHandle::Pair(handle) => {
+                    let (x, y) = VariantHandle::into_fields(handle);
+                    (2, x.into() + y.into())
+                }
+                EventHandle::Named(handle) => {
+                    let tag = handle.tag();
+                    mem::drop(VariantHandle::into_handle(handle));
+                    (tag, 0)
+                }
+            };
+            LOG.with(|log| log.borrow_mut().push(entry));
+        }
+    }
+
+    #[test]
+    fn variants() {
+        mem::drop(Event::<u32>::from(EventInner::Empty));
+        mem::drop(Event::from(EventInner::Single(5u32)));
+        mem::drop(Event::from(EventInner::Pair(2u32, 3)));
+
+        let list = Rc::new(RefCell::new(Vec::new()));
+        mem::drop(Event::from(EventInner::Named {
+            first: 1u32,
+            second: list.clone(),
+        }));
+        assert_eq!(Rc::strong_count(&list), 1);
+
+        LOG.with(|log| assert_eq!(*log.borrow(), [(0, 0), (1, 5), (2, 5), (3, 0)]));
+    }
+}